@@ -0,0 +1,236 @@
+// == Band Notes ==
+
+// Scales
+// Linear bands are all the same width in Hz, which spends most of them on treble. We hear
+// pitch logarithmically, so log bands (and octave and 1/3 octave bands, which are log bands
+// with standard widths) give bass and treble the same share.
+// Reference: https://en.wikipedia.org/wiki/Mel_scale
+// The mel scale is roughly linear below 1kHz and logarithmic above it.
+
+// Narrow bands
+// At low frequencies a log band can be narrower than an FFT bin and contain no bins at all.
+// Those bands take the amplitude interpolated at their center frequency instead.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BandScale {
+    Linear,
+    Log,
+    Octave,
+    ThirdOctave,
+    Mel,
+}
+
+impl BandScale {
+    pub fn from_name(name: &str) -> Option<BandScale> {
+        match name {
+            "linear" => Some(BandScale::Linear),
+            "log" => Some(BandScale::Log),
+            "octave" => Some(BandScale::Octave),
+            "third_octave" => Some(BandScale::ThirdOctave),
+            "mel" => Some(BandScale::Mel),
+
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct BandConfig {
+    pub scale: BandScale,
+    // Ignored by the octave scales, whose band count follows from the frequency range.
+    pub band_count: usize,
+    pub min_hz: f32,
+    pub max_hz: f32,
+
+    // Upper edges of the low, mid and high power ranges.
+    pub low_threshold_hz: f32,
+    pub mid_threshold_hz: f32,
+    pub high_threshold_hz: f32,
+}
+
+pub struct BandLayout {
+    edges: Vec<f32>,
+    centers: Vec<f32>,
+}
+
+impl BandLayout {
+    pub fn new(config: &BandConfig, sample_rate: u32) -> BandLayout {
+        let min_hz = f32::max(1.0, config.min_hz);
+        let max_hz = f32::max(min_hz * 2.0, f32::min(config.max_hz, sample_rate as f32 / 2.0));
+        let band_count = usize::max(1, config.band_count);
+
+        let edges: Vec<f32> = match config.scale {
+            BandScale::Linear => (0..band_count + 1)
+                .map(|i| min_hz + (max_hz - min_hz) * i as f32 / band_count as f32)
+                .collect(),
+            BandScale::Log => (0..band_count + 1)
+                .map(|i| min_hz * (max_hz / min_hz).powf(i as f32 / band_count as f32))
+                .collect(),
+            BandScale::Octave => fractional_octave_edges(min_hz, max_hz, 1.0),
+            BandScale::ThirdOctave => fractional_octave_edges(min_hz, max_hz, 3.0),
+            BandScale::Mel => {
+                let min_mel = hz_to_mel(min_hz);
+                let max_mel = hz_to_mel(max_hz);
+                (0..band_count + 1)
+                    .map(|i| mel_to_hz(min_mel + (max_mel - min_mel) * i as f32 / band_count as f32))
+                    .collect()
+            }
+        };
+
+        let centers = match config.scale {
+            BandScale::Linear => edges.windows(2).map(|edge| (edge[0] + edge[1]) / 2.0).collect(),
+            _ => edges.windows(2).map(|edge| (edge[0] * edge[1]).sqrt()).collect(),
+        };
+
+        BandLayout {
+            edges,
+            centers,
+        }
+    }
+
+    pub fn band_count(&self) -> usize {
+        self.centers.len()
+    }
+
+    // Center frequency of each band, in Hz.
+    pub fn centers(&self) -> &[f32] {
+        &self.centers
+    }
+
+    // Sum the amplitudes of the frequency bins that fall in each band.
+    pub fn compute(&self, frequency_bins: &[f32], amplitudes: &[f32]) -> Vec<f32> {
+        let mut bands = vec![0.0; self.band_count()];
+        let mut bin_counts = vec![0; self.band_count()];
+
+        let mut band = 0;
+        for i in 0..frequency_bins.len() {
+            while band < bands.len() && frequency_bins[i] >= self.edges[band + 1] {
+                band += 1;
+            }
+            if band >= bands.len() {
+                break;
+            }
+            if frequency_bins[i] >= self.edges[band] {
+                bands[band] += amplitudes[i];
+                bin_counts[band] += 1;
+            }
+        }
+
+        for band in 0..bands.len() {
+            if bin_counts[band] == 0 {
+                bands[band] = interpolate(frequency_bins, amplitudes, self.centers[band]);
+            }
+        }
+
+        bands
+    }
+}
+
+fn fractional_octave_edges(min_hz: f32, max_hz: f32, bands_per_octave: f32) -> Vec<f32> {
+    let band_count = usize::max(1, ((max_hz / min_hz).log2() * bands_per_octave).floor() as usize);
+
+    (0..band_count + 1)
+        .map(|i| min_hz * 2f32.powf(i as f32 / bands_per_octave))
+        .collect()
+}
+
+fn hz_to_mel(hz: f32) -> f32 {
+    2595.0 * (1.0 + hz / 700.0).log10()
+}
+
+fn mel_to_hz(mel: f32) -> f32 {
+    700.0 * (10f32.powf(mel / 2595.0) - 1.0)
+}
+
+// Linearly interpolate the amplitude at a frequency between the two nearest bins.
+fn interpolate(frequency_bins: &[f32], amplitudes: &[f32], frequency: f32) -> f32 {
+    if frequency_bins.len() < 2 {
+        return 0.0;
+    }
+
+    let bin_width = frequency_bins[1] - frequency_bins[0];
+    let position = frequency / bin_width;
+    let index = f32::min(position.floor(), (frequency_bins.len() - 2) as f32) as usize;
+    let fraction = f32::min(1.0, position - index as f32);
+
+    amplitudes[index] * (1.0 - fraction) + amplitudes[index + 1] * fraction
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(scale: BandScale, band_count: usize) -> BandConfig {
+        BandConfig {
+            scale,
+            band_count,
+            min_hz: 40.0,
+            max_hz: 16000.0,
+            low_threshold_hz: 1000.0,
+            mid_threshold_hz: 4000.0,
+            high_threshold_hz: 20000.0,
+        }
+    }
+
+    #[test]
+    fn layouts_cover_the_range_in_order() {
+        for &scale in &[BandScale::Linear, BandScale::Log, BandScale::Octave,
+                        BandScale::ThirdOctave, BandScale::Mel] {
+            let layout = BandLayout::new(&config(scale, 16), 48000);
+            assert_eq!(layout.edges.len(), layout.band_count() + 1);
+            assert!((layout.edges[0] - 40.0).abs() < 1e-3, "{:?}", scale);
+            assert!(*layout.edges.last().unwrap() <= 16000.5, "{:?}", scale);
+            assert!(layout.edges.windows(2).all(|edge| edge[0] < edge[1]), "{:?}", scale);
+            for (center, edge) in layout.centers().iter().zip(layout.edges.windows(2)) {
+                assert!(edge[0] < *center && *center < edge[1], "{:?}", scale);
+            }
+        }
+    }
+
+    #[test]
+    fn octave_band_counts_follow_the_range() {
+        // 40 Hz to 16 kHz is a little under 9 octaves.
+        assert_eq!(BandLayout::new(&config(BandScale::Octave, 16), 48000).band_count(), 8);
+        assert_eq!(BandLayout::new(&config(BandScale::ThirdOctave, 16), 48000).band_count(), 25);
+        assert_eq!(BandLayout::new(&config(BandScale::Log, 16), 48000).band_count(), 16);
+    }
+
+    #[test]
+    fn max_hz_is_limited_to_nyquist() {
+        let layout = BandLayout::new(&config(BandScale::Log, 10), 8000);
+        assert!((*layout.edges.last().unwrap() - 4000.0).abs() < 1e-2);
+    }
+
+    #[test]
+    fn compute_sums_the_bins_in_each_band() {
+        let layout = BandLayout {
+            edges: vec![100.0, 300.0, 600.0],
+            centers: vec![200.0, 450.0],
+        };
+        let bins: Vec<f32> = (0..10).map(|i| i as f32 * 100.0).collect();
+        let amplitudes: Vec<f32> = (0..10).map(|i| i as f32).collect();
+
+        // 100 and 200 Hz in the first band, 300 to 500 Hz in the second.
+        assert_eq!(layout.compute(&bins, &amplitudes), vec![1.0 + 2.0, 3.0 + 4.0 + 5.0]);
+    }
+
+    #[test]
+    fn narrow_bands_interpolate() {
+        let layout = BandLayout {
+            edges: vec![110.0, 130.0],
+            centers: vec![120.0],
+        };
+        let bins = [0.0, 100.0, 200.0, 300.0];
+        let amplitudes = [0.0, 1.0, 3.0, 0.0];
+
+        let bands = layout.compute(&bins, &amplitudes);
+        assert!((bands[0] - 1.4).abs() < 1e-5);
+    }
+
+    #[test]
+    fn scales_by_name() {
+        assert_eq!(BandScale::from_name("third_octave"), Some(BandScale::ThirdOctave));
+        assert_eq!(BandScale::from_name("mel"), Some(BandScale::Mel));
+        assert_eq!(BandScale::from_name("bark"), None);
+    }
+}
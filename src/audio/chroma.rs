@@ -0,0 +1,145 @@
+// == Chroma Notes ==
+
+// Chromagram
+// Reference: https://en.wikipedia.org/wiki/Chroma_feature
+// Folds the spectrum into the 12 pitch classes (C, C#, ... B) regardless of octave, so a
+// chord looks the same whether it's played low or high. Peaks outside the range of most
+// melodies and chords are left out, since drums and hiss would only add noise.
+
+// Key estimation
+// Reference: Krumhansl, "Cognitive Foundations of Musical Pitch" (1990), the
+// Krumhansl-Schmuckler key-finding algorithm.
+// Each key has a profile of how often each pitch class sounds in it. The key whose profile
+// (rotated to its tonic) correlates best with the chroma averaged over the last few
+// seconds is the estimate.
+
+pub const PITCH_CLASS_COUNT: usize = 12;
+
+const MIN_HZ: f32 = 55.0;
+const MAX_HZ: f32 = 5000.0;
+
+const KEY_HISTORY_SECONDS: f32 = 8.0;
+
+static MAJOR_PROFILE: [f32; PITCH_CLASS_COUNT] =
+    [6.35, 2.23, 3.48, 2.33, 4.38, 4.09, 2.52, 5.19, 2.39, 3.66, 2.29, 2.88];
+static MINOR_PROFILE: [f32; PITCH_CLASS_COUNT] =
+    [6.33, 2.68, 3.52, 5.38, 2.60, 3.53, 2.54, 4.75, 3.98, 2.69, 3.34, 3.17];
+
+// The energy of each pitch class, starting at C, scaled so the strongest is 1.
+pub fn chroma(frequency_bins: &[f32], amplitudes: &[f32]) -> [f32; PITCH_CLASS_COUNT] {
+    let mut output = [0.0; PITCH_CLASS_COUNT];
+    if frequency_bins.len() < 3 {
+        return output;
+    }
+    let bin_width = frequency_bins[1] - frequency_bins[0];
+
+    // Bins are wider than a semitone in the lower octaves, so only spectral peaks are
+    // counted, at the frequency interpolated between their neighbours.
+    for i in 1..frequency_bins.len() - 1 {
+        let (left, center, right) = (amplitudes[i - 1], amplitudes[i], amplitudes[i + 1]);
+        if center <= left || center < right {
+            continue;
+        }
+
+        let curvature = left - 2.0 * center + right;
+        let offset = if curvature < 0.0 { 0.5 * (left - right) / curvature } else { 0.0 };
+        let frequency = frequency_bins[i] + offset * bin_width;
+        if !(MIN_HZ..=MAX_HZ).contains(&frequency) {
+            continue;
+        }
+
+        // MIDI note 69 is A4 (440hz), and MIDI note 0 is a C.
+        let note = (12.0 * (frequency / 440.0).log2() + 69.0).round() as i32;
+        let pitch_class = ((note % 12 + 12) % 12) as usize;
+        output[pitch_class] += center * center;
+    }
+
+    let max = output.iter().cloned().fold(0.0, f32::max);
+    if max > 0.0 {
+        for value in output.iter_mut() {
+            *value /= max;
+        }
+    }
+
+    output
+}
+
+pub struct KeyEstimator {
+    smoothing: f32,
+    average_chroma: [f32; PITCH_CLASS_COUNT],
+
+    key: usize,
+    is_minor: bool,
+    confidence: f32,
+}
+
+impl KeyEstimator {
+    pub fn new(frames_per_second: f32) -> KeyEstimator {
+        KeyEstimator {
+            smoothing: 1.0 - (-1.0 / (KEY_HISTORY_SECONDS * frames_per_second)).exp(),
+            average_chroma: [0.0; PITCH_CLASS_COUNT],
+
+            key: 0,
+            is_minor: false,
+            confidence: 0.0,
+        }
+    }
+
+    // The pitch class of the tonic, from 0 (C) to 11 (B).
+    pub fn key(&self) -> usize {
+        self.key
+    }
+
+    pub fn is_minor(&self) -> bool {
+        self.is_minor
+    }
+
+    // The correlation between the recent chroma and the key's profile, from 0 to 1.
+    pub fn confidence(&self) -> f32 {
+        self.confidence
+    }
+
+    pub fn update(&mut self, chroma: &[f32; PITCH_CLASS_COUNT]) {
+        for (average, &value) in self.average_chroma.iter_mut().zip(chroma.iter()) {
+            *average += (value - *average) * self.smoothing;
+        }
+
+        let mut best_correlation = 0.0;
+        for tonic in 0..PITCH_CLASS_COUNT {
+            for &(profile, is_minor) in [(&MAJOR_PROFILE, false), (&MINOR_PROFILE, true)].iter() {
+                let correlation = rotated_correlation(&self.average_chroma, profile, tonic);
+                if correlation > best_correlation {
+                    best_correlation = correlation;
+                    self.key = tonic;
+                    self.is_minor = is_minor;
+                }
+            }
+        }
+
+        self.confidence = best_correlation;
+    }
+}
+
+// Pearson correlation between the chroma and the profile shifted to start at the tonic.
+fn rotated_correlation(chroma: &[f32; PITCH_CLASS_COUNT], profile: &[f32; PITCH_CLASS_COUNT],
+                       tonic: usize) -> f32 {
+    let chroma_mean = chroma.iter().sum::<f32>() / PITCH_CLASS_COUNT as f32;
+    let profile_mean = profile.iter().sum::<f32>() / PITCH_CLASS_COUNT as f32;
+
+    let mut covariance = 0.0;
+    let mut chroma_variance = 0.0;
+    let mut profile_variance = 0.0;
+    for i in 0..PITCH_CLASS_COUNT {
+        let c = chroma[(i + tonic) % PITCH_CLASS_COUNT] - chroma_mean;
+        let p = profile[i] - profile_mean;
+        covariance += c * p;
+        chroma_variance += c * c;
+        profile_variance += p * p;
+    }
+
+    if chroma_variance <= 0.0 {
+        return 0.0;
+    }
+
+    covariance / (chroma_variance * profile_variance).sqrt()
+}
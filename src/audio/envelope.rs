@@ -0,0 +1,87 @@
+// == Envelope Notes ==
+
+// Attack and decay
+// Reference: https://en.wikipedia.org/wiki/Envelope_detector
+// Each value is followed by a one pole filter that rises with the attack time and falls
+// with the decay time, so displays can jump with a hit but fall back smoothly instead of
+// flickering at the FFT frame rate.
+
+// Peak hold
+// Like the peak markers on a hardware spectrum analyzer: the peak jumps to any new
+// maximum, holds there for a moment, then falls with gravity, speeding up the longer it
+// falls.
+
+#[derive(Clone)]
+pub struct EnvelopeConfig {
+    pub attack_seconds: f32,
+    pub decay_seconds: f32,
+    pub peak_hold_seconds: f32,
+    // Acceleration of a falling peak, in units per second squared.
+    pub peak_gravity: f32,
+}
+
+pub struct EnvelopeFollower {
+    attack: f32,
+    decay: f32,
+    peak_hold_frames: u32,
+    // Added to a falling peak's speed each frame.
+    peak_acceleration: f32,
+
+    smoothed: Vec<f32>,
+    peaks: Vec<f32>,
+    peak_ages: Vec<u32>,
+    peak_speeds: Vec<f32>,
+}
+
+impl EnvelopeFollower {
+    pub fn new(config: &EnvelopeConfig, frames_per_second: f32) -> EnvelopeFollower {
+        // A time of 0 follows the input exactly.
+        let smoothing = |seconds: f32| 1.0 - (-1.0 / f32::max(1e-3, seconds * frames_per_second)).exp();
+
+        EnvelopeFollower {
+            attack: smoothing(config.attack_seconds),
+            decay: smoothing(config.decay_seconds),
+            peak_hold_frames: (config.peak_hold_seconds * frames_per_second).round() as u32,
+            peak_acceleration: config.peak_gravity / (frames_per_second * frames_per_second),
+
+            smoothed: Vec::new(),
+            peaks: Vec::new(),
+            peak_ages: Vec::new(),
+            peak_speeds: Vec::new(),
+        }
+    }
+
+    pub fn smoothed(&self) -> &[f32] {
+        &self.smoothed
+    }
+
+    pub fn peaks(&self) -> &[f32] {
+        &self.peaks
+    }
+
+    pub fn update(&mut self, values: &[f32]) {
+        if values.len() != self.smoothed.len() {
+            self.smoothed = values.to_vec();
+            self.peaks = values.to_vec();
+            self.peak_ages = vec![0; values.len()];
+            self.peak_speeds = vec![0.0; values.len()];
+            return;
+        }
+
+        for (i, &value) in values.iter().enumerate() {
+            let speed = if value > self.smoothed[i] { self.attack } else { self.decay };
+            self.smoothed[i] += (value - self.smoothed[i]) * speed;
+
+            if value >= self.peaks[i] {
+                self.peaks[i] = value;
+                self.peak_ages[i] = 0;
+                self.peak_speeds[i] = 0.0;
+            } else if self.peak_ages[i] < self.peak_hold_frames {
+                self.peak_ages[i] += 1;
+            } else {
+                self.peak_speeds[i] += self.peak_acceleration;
+                self.peaks[i] = f32::max(value, self.peaks[i] - self.peak_speeds[i]);
+            }
+        }
+    }
+}
@@ -0,0 +1,68 @@
+// == Feature Notes ==
+
+// Reference: Peeters, "A large set of audio features for sound description" (2004).
+// Timbre descriptors that summarize a whole spectrum in a single number. They only depend
+// on the shape of the spectrum, not its level, and the listener computes them at a fixed
+// gain, ahead of the automatic gain control and noise gate, so they are as meaningful for
+// a quiet room as for a loud club.
+
+// Below this much energy the spectrum is treated as silence, and every feature is 0.
+const MIN_ENERGY: f32 = 1e-12;
+
+// The fraction of the energy that lies below the rolloff frequency.
+const ROLLOFF_FRACTION: f32 = 0.85;
+
+// The amplitude weighted mean frequency, in Hz. Higher means brighter.
+pub fn spectral_centroid(frequency_bins: &[f32], amplitudes: &[f32]) -> f32 {
+    let total: f32 = amplitudes.iter().sum();
+    if total <= MIN_ENERGY {
+        return 0.0;
+    }
+
+    let weighted: f32 = frequency_bins.iter().zip(amplitudes.iter()).map(|(f, a)| f * a).sum();
+    weighted / total
+}
+
+// The frequency below which most of the energy lies, in Hz.
+pub fn spectral_rolloff(frequency_bins: &[f32], amplitudes: &[f32]) -> f32 {
+    let total: f32 = amplitudes.iter().map(|a| a * a).sum();
+    if total <= MIN_ENERGY {
+        return 0.0;
+    }
+
+    let mut energy = 0.0;
+    for i in 0..amplitudes.len() {
+        energy += amplitudes[i] * amplitudes[i];
+        if energy >= ROLLOFF_FRACTION * total {
+            return frequency_bins[i];
+        }
+    }
+
+    frequency_bins[frequency_bins.len() - 1]
+}
+
+// The geometric mean of the power spectrum over its arithmetic mean, from 0 to 1.
+// Close to 1 for noise, close to 0 for a few clear tones.
+pub fn spectral_flatness(amplitudes: &[f32]) -> f32 {
+    let count = amplitudes.len() as f32;
+    let arithmetic_mean = amplitudes.iter().map(|a| a * a).sum::<f32>() / count;
+    if arithmetic_mean <= MIN_ENERGY {
+        return 0.0;
+    }
+
+    let log_mean = amplitudes.iter().map(|a| (a * a + MIN_ENERGY).ln()).sum::<f32>() / count;
+    f32::min(1.0, log_mean.exp() / arithmetic_mean)
+}
+
+// The fraction of consecutive samples that change sign, from 0 to 1. Noisy and bright
+// sounds cross zero often, low tones rarely.
+pub fn zero_crossing_rate(samples: &[f32]) -> f32 {
+    if samples.len() < 2 {
+        return 0.0;
+    }
+
+    let crossings = samples.windows(2)
+        .filter(|pair| (pair[0] >= 0.0) != (pair[1] >= 0.0))
+        .count();
+    crossings as f32 / (samples.len() - 1) as f32
+}
@@ -0,0 +1,163 @@
+pub fn frequency_bins(sample_rate: u32, sample_count: u32) -> Vec<f32> {
+    let bin_count = (sample_count / 2) as usize;
+    let mut output: Vec<f32> = vec![0.0; bin_count];
+
+    for i in 0..bin_count {
+        output[i] = (i as f32) * (sample_rate as f32) / (sample_count as f32);
+    }
+
+    output
+}
+
+// Number of 100hz buckets needed to cover every frequency up to the Nyquist frequency.
+pub fn hundred_hz_bucket_count(sample_rate: u32) -> usize {
+    ((sample_rate as f32 / 2.0) / 100.0).ceil() as usize
+}
+
+// Spectral flux
+// Reference: Dixon, "Onset Detection Revisited" (2006).
+// The sum of increases in magnitude between two spectra. Log compression keeps loud bins
+// from drowning out the rest, and ignoring decreases means only new notes count.
+pub fn spectral_flux(previous: &[f32], current: &[f32]) -> f32 {
+    let mut flux = 0.0;
+
+    for i in 0..usize::min(previous.len(), current.len()) {
+        let increase = (1.0 + 100.0 * current[i]).ln() - (1.0 + 100.0 * previous[i]).ln();
+        if increase > 0.0 {
+            flux += increase;
+        }
+    }
+
+    flux
+}
+
+// Number of values in each of the frame's waveforms.
+pub const WAVEFORM_LENGTH: usize = 128;
+
+// Shrink a window of samples to `length` values, each the mean of an equal slice of the
+// window. Averaging rather than skipping samples keeps high frequencies from aliasing
+// into slow wobbles. Shorter windows are returned as they are.
+pub fn downsample(samples: &[f32], length: usize) -> Vec<f32> {
+    if samples.len() <= length {
+        return samples.to_vec();
+    }
+
+    (0..length).map(|i| {
+        let start = i * samples.len() / length;
+        let end = (i + 1) * samples.len() / length;
+        samples[start..end].iter().sum::<f32>() / (end - start) as f32
+    }).collect()
+}
+
+// Adding a field also means adding it to the frame recording format in listen/recording.rs.
+#[derive(Clone)]
+pub struct AudioFrame {
+    pub bpm: f32,
+    pub bpm_confidence: f32,
+    pub beat_phase: f32,
+    pub is_beat: bool,
+    pub onset_strength: f32,
+    // Whether the audio has been below the silence threshold for a moment.
+    pub silent: bool,
+    // How long the audio has been below the silence threshold, in seconds.
+    pub idle_seconds: f32,
+
+    pub low_power: f32,
+    pub mid_power: f32,
+    pub high_power: f32,
+    // One bucket per 100hz, up to the Nyquist frequency of the source's sample rate.
+    pub hundred_hz_buckets: Vec<f32>,
+
+    // The powers and buckets after the attack/decay envelope, for displays that shouldn't
+    // flicker with every frame.
+    pub smoothed_low_power: f32,
+    pub smoothed_mid_power: f32,
+    pub smoothed_high_power: f32,
+    pub smoothed_buckets: Vec<f32>,
+    // The recent peak of each bucket, held for a moment and then falling with gravity.
+    pub peak_buckets: Vec<f32>,
+
+    // Timbre descriptors of the whole spectrum.
+    // Amplitude weighted mean frequency, in Hz.
+    pub spectral_centroid: f32,
+    // Average increase in log amplitude per frequency bin since the previous window.
+    pub spectral_flux: f32,
+    // Frequency below which 85% of the energy lies, in Hz.
+    pub spectral_rolloff: f32,
+    // From 0 (pure tones) to 1 (white noise).
+    pub spectral_flatness: f32,
+    // Fraction of samples that change sign, from 0 to 1.
+    pub zero_crossing_rate: f32,
+
+    // Energy of each pitch class, starting at C, scaled so the strongest is 1.
+    pub chroma: [f32; 12],
+    // Estimated key of the last few seconds: the tonic's pitch class (0 is C) and mode.
+    pub key: usize,
+    pub key_is_minor: bool,
+    pub key_confidence: f32,
+
+    // Fundamental frequency of a single voice or instrument, or 0 when there is no clear pitch.
+    pub pitch_hz: f32,
+    pub pitch_confidence: f32,
+    // Nearest MIDI note (69 is A4) and how many cents the pitch is sharp (+) or flat (-).
+    pub pitch_note: i32,
+    pub pitch_cents: f32,
+
+    // One value per band of the configured band layout, lowest frequency first.
+    pub bands: Vec<f32>,
+    // The bands after the envelope, and their peaks, like the buckets above.
+    pub smoothed_bands: Vec<f32>,
+    pub peak_bands: Vec<f32>,
+
+    // The bands of each channel. Mono sources copy `bands` into both.
+    pub left_bands: Vec<f32>,
+    pub right_bands: Vec<f32>,
+    // From -1 (all left) to 1 (all right).
+    pub balance: f32,
+    // From 0 (mono) to 1 (channels out of phase).
+    pub width: f32,
+
+    // The window's samples before any gain, from -1 to 1, downsampled to WAVEFORM_LENGTH
+    // values with the oldest first. Mono sources copy `waveform` into both channels.
+    pub waveform: Vec<f32>,
+    pub left_waveform: Vec<f32>,
+    pub right_waveform: Vec<f32>,
+    // Root mean square and largest absolute value of the window's samples, before any gain.
+    pub rms: f32,
+    pub peak: f32,
+}
+
+impl AudioFrame {
+    // Carry on the events of a frame that was skipped on its way to the renderer, so a beat
+    // is never lost just because the renderer was busy.
+    pub fn merge_skipped(&mut self, skipped: AudioFrame) {
+        self.is_beat |= skipped.is_beat;
+        self.onset_strength = f32::max(self.onset_strength, skipped.onset_strength);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn downsample_averages_each_stretch() {
+        let samples: Vec<f32> = (0..8).map(|i| i as f32).collect();
+        assert_eq!(downsample(&samples, 4), vec![0.5, 2.5, 4.5, 6.5]);
+        assert_eq!(downsample(&samples, 1), vec![3.5]);
+    }
+
+    #[test]
+    fn downsample_handles_uneven_lengths() {
+        let samples = vec![1.0; 1023];
+        let downsampled = downsample(&samples, 256);
+        assert_eq!(downsampled.len(), 256);
+        assert!(downsampled.iter().all(|&x| x == 1.0));
+    }
+
+    #[test]
+    fn downsample_keeps_short_input() {
+        assert_eq!(downsample(&[0.25, -0.25], 256), vec![0.25, -0.25]);
+        assert!(downsample(&[], 256).is_empty());
+    }
+}
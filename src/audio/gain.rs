@@ -0,0 +1,133 @@
+// == Gain Notes ==
+
+// Attack and release
+// Reference: https://en.wikipedia.org/wiki/Dynamic_range_compression
+// The gain follows an envelope of the input level. The envelope rises quickly (attack) so a
+// sudden loud passage doesn't clip for long, and falls slowly (release) so the gain doesn't
+// pump up during short pauses.
+
+// Noise gate
+// Below the gate threshold the input is treated as silence: the envelope stops moving, so
+// the gain doesn't climb until room hiss fills the display, and the output fades to zero.
+
+// The average amplitude of a frequency bin that the gain aims for.
+const TARGET_LEVEL: f32 = 0.01;
+// The level that per band normalization aims for in each band.
+const BAND_TARGET_LEVEL: f32 = 0.25;
+// Per band normalization never changes a band by more than this factor either way.
+const MAX_BAND_CORRECTION: f32 = 10.0;
+// How long the gate takes to open or close.
+const GATE_SECONDS: f32 = 0.05;
+
+#[derive(Clone)]
+pub struct GainConfig {
+    // When false, the initial gain is used for everything.
+    pub automatic: bool,
+    pub initial_gain: f32,
+
+    pub attack_seconds: f32,
+    pub release_seconds: f32,
+    pub min_gain: f32,
+    pub max_gain: f32,
+    pub noise_gate_db: f32,
+    // Scale each band towards the same level, instead of only the overall level.
+    pub per_band: bool,
+}
+
+pub struct AutomaticGainControl {
+    config: GainConfig,
+    attack: f32,
+    release: f32,
+    gate_speed: f32,
+
+    envelope: f32,
+    gain: f32,
+    gate: f32,
+    band_envelopes: Vec<f32>,
+}
+
+impl AutomaticGainControl {
+    pub fn new(config: GainConfig, frames_per_second: f32) -> AutomaticGainControl {
+        let smoothing = |seconds: f32| 1.0 - (-1.0 / f32::max(1e-3, seconds * frames_per_second)).exp();
+        let initial_gain = f32::max(config.min_gain, f32::min(config.max_gain, config.initial_gain));
+
+        AutomaticGainControl {
+            attack: smoothing(config.attack_seconds),
+            release: smoothing(config.release_seconds),
+            gate_speed: smoothing(GATE_SECONDS),
+
+            envelope: TARGET_LEVEL / initial_gain,
+            gain: if config.automatic { initial_gain } else { config.initial_gain },
+            gate: 1.0,
+            band_envelopes: Vec::new(),
+
+            config,
+        }
+    }
+
+    // The gain for the latest window, including the noise gate.
+    pub fn gain(&self) -> f32 {
+        self.gain * self.gate
+    }
+
+    // Update the gain from the average amplitude of the latest window's frequency bins
+    // (before any gain) and the RMS of its samples.
+    pub fn update(&mut self, level: f32, rms: f32) {
+        if !self.config.automatic {
+            return;
+        }
+
+        let rms_db = 20.0 * f32::max(rms, 1e-10).log10();
+        let gate_open = rms_db >= self.config.noise_gate_db;
+        let gate_target = if gate_open { 1.0 } else { 0.0 };
+        self.gate += (gate_target - self.gate) * self.gate_speed;
+
+        if !gate_open {
+            return;
+        }
+
+        let speed = if level > self.envelope { self.attack } else { self.release };
+        self.envelope += (level - self.envelope) * speed;
+
+        self.gain = if self.envelope > 0.0 { TARGET_LEVEL / self.envelope } else { self.config.max_gain };
+        self.gain = f32::max(self.config.min_gain, f32::min(self.config.max_gain, self.gain));
+    }
+
+    // Scale each band towards the same level, using a separate envelope per band.
+    pub fn normalize_bands(&mut self, bands: &mut [f32]) {
+        if !self.config.automatic || !self.config.per_band {
+            return;
+        }
+
+        if self.band_envelopes.len() != bands.len() {
+            self.band_envelopes = vec![BAND_TARGET_LEVEL; bands.len()];
+        }
+
+        // Only learn from bands while there is something to hear.
+        if self.gate > 0.5 {
+            for (envelope, &band) in self.band_envelopes.iter_mut().zip(bands.iter()) {
+                let speed = if band > *envelope { self.attack } else { self.release };
+                *envelope += (band - *envelope) * speed;
+            }
+        }
+
+        self.correct_bands(bands);
+    }
+
+    // Scale bands by the corrections `normalize_bands` has learned, without learning from
+    // them. Used for the left and right bands, so they stay on the same scale as the mix.
+    pub fn correct_bands(&self, bands: &mut [f32]) {
+        if !self.config.automatic || !self.config.per_band || self.band_envelopes.len() != bands.len() {
+            return;
+        }
+
+        for (band, &envelope) in bands.iter_mut().zip(&self.band_envelopes) {
+            let correction = if envelope > 0.0 {
+                BAND_TARGET_LEVEL / envelope
+            } else {
+                MAX_BAND_CORRECTION
+            };
+            *band *= correction.clamp(1.0 / MAX_BAND_CORRECTION, MAX_BAND_CORRECTION);
+        }
+    }
+}
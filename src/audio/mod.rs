@@ -0,0 +1,21 @@
+mod bands;
+mod chroma;
+mod envelope;
+mod features;
+mod frame;
+mod gain;
+mod onset;
+mod pitch;
+mod spectrum;
+mod tempo;
+
+pub use self::bands::{BandConfig, BandLayout, BandScale};
+pub use self::chroma::{chroma, KeyEstimator};
+pub use self::envelope::{EnvelopeConfig, EnvelopeFollower};
+pub use self::features::{spectral_centroid, spectral_flatness, spectral_rolloff, zero_crossing_rate};
+pub use self::frame::*;
+pub use self::gain::{AutomaticGainControl, GainConfig};
+pub use self::onset::OnsetDetector;
+pub use self::pitch::PitchDetector;
+pub use self::spectrum::{SpectrumAnalyzer, WindowFunction};
+pub use self::tempo::TempoTracker;
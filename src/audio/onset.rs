@@ -0,0 +1,117 @@
+use audio::frame::spectral_flux;
+use std::collections::VecDeque;
+
+// == Onset Notes ==
+
+// Adaptive threshold
+// Reference: Bello et al., "A Tutorial on Onset Detection in Music Signals" (2005), section 5.
+// Spectral flux rises whenever a note or drum hit starts, but how much depends on the
+// music. Comparing each frame against the median of the recent flux (plus a small offset
+// relative to the loudest recent flux) picks out onsets in both quiet and busy passages.
+
+const THRESHOLD_SECONDS: f32 = 0.5;
+const THRESHOLD_MULTIPLIER: f32 = 1.5;
+const THRESHOLD_PEAK_OFFSET: f32 = 0.1;
+
+// Nothing is fast enough to need onsets closer together than this (a 16th note at 150 BPM).
+const MIN_SECONDS_BETWEEN_ONSETS: f32 = 0.1;
+
+// How long the loudest flux takes to fall to 1/e of its value, used to normalize strength.
+const PEAK_DECAY_SECONDS: f32 = 5.0;
+
+pub struct OnsetDetector {
+    previous_amplitudes: Vec<f32>,
+    flux_history: VecDeque<f32>,
+    history_len: usize,
+
+    peak_flux: f32,
+    peak_decay: f32,
+
+    frames_since_onset: usize,
+    min_frames_between_onsets: usize,
+
+    flux: f32,
+    strength: f32,
+    is_onset: bool,
+}
+
+impl OnsetDetector {
+    pub fn new(frames_per_second: f32) -> OnsetDetector {
+        let history_len = usize::max(3, (THRESHOLD_SECONDS * frames_per_second).ceil() as usize);
+        let min_frames_between_onsets = (MIN_SECONDS_BETWEEN_ONSETS * frames_per_second).ceil() as usize;
+
+        OnsetDetector {
+            previous_amplitudes: Vec::new(),
+            flux_history: VecDeque::with_capacity(history_len),
+            history_len,
+
+            peak_flux: 0.0,
+            peak_decay: (-1.0 / (PEAK_DECAY_SECONDS * frames_per_second)).exp(),
+
+            frames_since_onset: min_frames_between_onsets,
+            min_frames_between_onsets,
+
+            flux: 0.0,
+            strength: 0.0,
+            is_onset: false,
+        }
+    }
+
+    // The raw spectral flux of the latest frame.
+    pub fn flux(&self) -> f32 {
+        self.flux
+    }
+
+    // The spectral flux of the latest frame relative to the loudest recent flux, from 0 to 1.
+    pub fn strength(&self) -> f32 {
+        self.strength
+    }
+
+    // Whether an onset started in the latest frame.
+    pub fn is_onset(&self) -> bool {
+        self.is_onset
+    }
+
+    pub fn update(&mut self, amplitudes: &[f32]) {
+        let flux = spectral_flux(&self.previous_amplitudes, amplitudes);
+        self.frames_since_onset += 1;
+
+        // A NaN or infinite sample from the input would otherwise stay in the history and
+        // poison every threshold after it. Skip the frame and start over from the next one.
+        if !flux.is_finite() {
+            self.previous_amplitudes.clear();
+            self.flux = 0.0;
+            self.strength = 0.0;
+            self.is_onset = false;
+            return;
+        }
+
+        self.flux = flux;
+        self.previous_amplitudes.clear();
+        self.previous_amplitudes.extend_from_slice(amplitudes);
+
+        self.peak_flux = f32::max(self.flux, self.peak_flux * self.peak_decay);
+        self.strength = if self.peak_flux > 0.0 { self.flux / self.peak_flux } else { 0.0 };
+
+        let mut sorted_history: Vec<f32> = self.flux_history.iter().cloned().collect();
+        sorted_history.sort_by(f32::total_cmp);
+        let median = if sorted_history.is_empty() {
+            0.0
+        } else {
+            sorted_history[sorted_history.len() / 2]
+        };
+        let threshold = median * THRESHOLD_MULTIPLIER + self.peak_flux * THRESHOLD_PEAK_OFFSET;
+
+        self.is_onset = self.flux_history.len() == self.history_len &&
+            self.flux > threshold &&
+            self.frames_since_onset >= self.min_frames_between_onsets;
+        if self.is_onset {
+            self.frames_since_onset = 0;
+        }
+
+        self.flux_history.push_back(self.flux);
+        if self.flux_history.len() > self.history_len {
+            self.flux_history.pop_front();
+        }
+    }
+}
@@ -0,0 +1,211 @@
+// == Pitch Notes ==
+
+// YIN
+// Reference: de Cheveigné and Kawahara, "YIN, a fundamental frequency estimator for speech
+// and music" (2002), http://audition.ens.fr/adc/pdf/2002_JASA_YIN.pdf
+// For each lag (tau), the difference function sums the squared difference between the
+// window and itself shifted by tau. A periodic signal differs very little from itself
+// shifted by one period. Normalizing by the running mean of the difference function
+// removes the bias towards tau = 0, and the first lag that dips below a threshold is taken
+// as the period, refined by parabolic interpolation between neighbouring lags.
+// This only works for one note at a time (a voice or a solo instrument), not chords.
+
+// Notes
+// MIDI note 69 is A4 (440hz), and each semitone is 100 cents.
+
+const MIN_HZ: f32 = 60.0;
+const MAX_HZ: f32 = 1500.0;
+
+// Dips in the normalized difference below this count as a period.
+const THRESHOLD: f32 = 0.15;
+// Below this confidence the window is treated as unpitched.
+const MIN_CONFIDENCE: f32 = 0.5;
+
+pub struct PitchDetector {
+    sample_rate: f32,
+    difference: Vec<f32>,
+
+    pitch_hz: f32,
+    confidence: f32,
+}
+
+impl PitchDetector {
+    pub fn new(sample_rate: u32) -> PitchDetector {
+        PitchDetector {
+            sample_rate: sample_rate as f32,
+            difference: Vec::new(),
+
+            pitch_hz: 0.0,
+            confidence: 0.0,
+        }
+    }
+
+    // The fundamental frequency of the last window, or 0 when it had no clear pitch.
+    pub fn pitch_hz(&self) -> f32 {
+        self.pitch_hz
+    }
+
+    // How periodic the last window was, from 0 to 1.
+    pub fn confidence(&self) -> f32 {
+        self.confidence
+    }
+
+    // The nearest MIDI note and how many cents the pitch is above (or below) it.
+    pub fn note(&self) -> (i32, f32) {
+        if self.pitch_hz <= 0.0 {
+            return (0, 0.0);
+        }
+
+        let exact = 12.0 * (self.pitch_hz / 440.0).log2() + 69.0;
+        let note = exact.round();
+        (note as i32, (exact - note) * 100.0)
+    }
+
+    // Forget the last pitch, e.g. while the input is silent.
+    pub fn reset(&mut self) {
+        self.pitch_hz = 0.0;
+        self.confidence = 0.0;
+    }
+
+    pub fn update(&mut self, samples: &[f32]) {
+        self.reset();
+
+        // Each lag needs a full half window to compare against.
+        let max_tau = usize::min((self.sample_rate / MIN_HZ) as usize, samples.len() / 2);
+        let min_tau = usize::max(2, (self.sample_rate / MAX_HZ) as usize);
+        if max_tau <= min_tau + 1 {
+            return;
+        }
+        let length = samples.len() - max_tau;
+
+        self.difference.clear();
+        self.difference.resize(max_tau + 1, 0.0);
+        for tau in 1..max_tau + 1 {
+            let mut sum = 0.0;
+            for i in 0..length {
+                let delta = samples[i] - samples[i + tau];
+                sum += delta * delta;
+            }
+            self.difference[tau] = sum;
+        }
+
+        // Cumulative mean normalized difference.
+        self.difference[0] = 1.0;
+        let mut running_sum = 0.0;
+        for tau in 1..max_tau + 1 {
+            running_sum += self.difference[tau];
+            self.difference[tau] = if running_sum > 0.0 {
+                self.difference[tau] * tau as f32 / running_sum
+            } else {
+                1.0
+            };
+        }
+
+        // The first dip below the threshold, followed down to its minimum. If there is no
+        // dip, fall back to the lowest point overall so the confidence is still meaningful.
+        let mut best_tau = min_tau;
+        let mut tau = min_tau;
+        while tau < max_tau {
+            if self.difference[tau] < THRESHOLD {
+                while tau + 1 < max_tau && self.difference[tau + 1] < self.difference[tau] {
+                    tau += 1;
+                }
+                best_tau = tau;
+                break;
+            }
+            if self.difference[tau] < self.difference[best_tau] {
+                best_tau = tau;
+            }
+            tau += 1;
+        }
+
+        let confidence = f32::max(0.0, 1.0 - self.difference[best_tau]);
+        if confidence < MIN_CONFIDENCE {
+            return;
+        }
+
+        let (left, center, right) = (
+            self.difference[best_tau - 1],
+            self.difference[best_tau],
+            self.difference[best_tau + 1],
+        );
+        // The vertex only lies between the neighbours when the center is the lowest of the
+        // three, which the fallback and the edge of the lag range don't guarantee.
+        let curvature = left - 2.0 * center + right;
+        let offset = if curvature > 0.0 { 0.5 * (left - right) / curvature } else { 0.0 };
+        let offset = offset.clamp(-1.0, 1.0);
+
+        self.pitch_hz = self.sample_rate / (best_tau as f32 + offset);
+        self.confidence = confidence;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::f32::consts::PI;
+
+    const SAMPLE_RATE: u32 = 24000;
+    const WINDOW_SIZE: usize = 1024;
+
+    fn sine(hz: f32) -> Vec<f32> {
+        (0..WINDOW_SIZE)
+            .map(|i| 0.5 * (2.0 * PI * hz * i as f32 / SAMPLE_RATE as f32).sin())
+            .collect()
+    }
+
+    #[test]
+    fn finds_a4() {
+        let mut detector = PitchDetector::new(SAMPLE_RATE);
+        detector.update(&sine(440.0));
+
+        assert!((detector.pitch_hz() - 440.0).abs() < 1.0, "{}", detector.pitch_hz());
+        assert!(detector.confidence() > 0.9, "{}", detector.confidence());
+        let (note, cents) = detector.note();
+        assert_eq!(note, 69);
+        assert!(cents.abs() < 5.0, "{}", cents);
+    }
+
+    #[test]
+    fn finds_notes_away_from_a4() {
+        let mut detector = PitchDetector::new(SAMPLE_RATE);
+        // A2, and C6 25 cents sharp, where the lag is only around 22 samples.
+        for &(hz, expected_note, expected_cents) in &[(110.0, 45, 0.0), (1061.7, 84, 25.0)] {
+            detector.update(&sine(hz));
+            let (note, cents) = detector.note();
+            assert_eq!(note, expected_note, "{}", hz);
+            assert!((cents - expected_cents).abs() < 5.0, "{} {}", hz, cents);
+        }
+    }
+
+    #[test]
+    fn silence_has_no_pitch() {
+        let mut detector = PitchDetector::new(SAMPLE_RATE);
+        detector.update(&vec![0.0; WINDOW_SIZE]);
+
+        assert_eq!(detector.pitch_hz(), 0.0);
+        assert_eq!(detector.confidence(), 0.0);
+        assert_eq!(detector.note(), (0, 0.0));
+    }
+
+    #[test]
+    fn white_noise_has_no_pitch() {
+        let mut rng = StdRng::from_seed([7; 32]);
+        let mut detector = PitchDetector::new(SAMPLE_RATE);
+        for _ in 0..10 {
+            let noise: Vec<f32> = (0..WINDOW_SIZE).map(|_| rng.gen_range(-0.5, 0.5)).collect();
+            detector.update(&noise);
+            assert_eq!(detector.pitch_hz(), 0.0);
+        }
+    }
+
+    #[test]
+    fn a_pitch_is_forgotten_on_the_next_window() {
+        let mut detector = PitchDetector::new(SAMPLE_RATE);
+        detector.update(&sine(440.0));
+        detector.update(&vec![0.0; WINDOW_SIZE]);
+        assert_eq!(detector.pitch_hz(), 0.0);
+    }
+}
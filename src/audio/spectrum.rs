@@ -0,0 +1,131 @@
+use audio::frame::frequency_bins;
+use rustfft::FFT;
+use rustfft::FFTplanner;
+use rustfft::num_complex::Complex;
+use rustfft::num_traits::Zero;
+use std::f32;
+use std::sync::Arc;
+
+// == FFT Notes ==
+
+// Frequencies
+// Reference: https://stackoverflow.com/questions/4364823/
+// Each bin of FFT output represents frequency [i * sample_rate / sample_count].
+// N / 2 will represent the Nyquist frequency.
+
+// Why is it a complex number?
+// Reference: https://www.gaussianwaves.com/2015/11/interpreting-fft
+// The combination of real and complex represents the amplitude and phase of that frequency bucket.
+
+// Windowing
+// Reference: Harris, "On the Use of Windows for Harmonic Analysis with the DFT" (1978).
+// Cutting a window out of a signal makes its edges jump, which smears energy across the
+// whole spectrum. Tapering the edges to zero trades a little frequency resolution for far
+// less of that leakage. The taper also throws away the signal near the edges, which is why
+// tapered windows should overlap (a hop of half a window or less).
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WindowFunction {
+    Rectangular,
+    Hann,
+    Hamming,
+    BlackmanHarris,
+}
+
+impl WindowFunction {
+    pub fn from_name(name: &str) -> Option<WindowFunction> {
+        match name {
+            "rectangular" => Some(WindowFunction::Rectangular),
+            "hann" => Some(WindowFunction::Hann),
+            "hamming" => Some(WindowFunction::Hamming),
+            "blackman_harris" => Some(WindowFunction::BlackmanHarris),
+
+            _ => None,
+        }
+    }
+
+    pub fn coefficients(&self, size: usize) -> Vec<f32> {
+        let mut output = vec![1.0; size];
+        if size < 2 {
+            return output;
+        }
+
+        for (i, coefficient) in output.iter_mut().enumerate() {
+            let x = 2.0 * f32::consts::PI * i as f32 / (size - 1) as f32;
+            *coefficient = match *self {
+                WindowFunction::Rectangular => 1.0,
+                WindowFunction::Hann => 0.5 - 0.5 * x.cos(),
+                WindowFunction::Hamming => 0.54 - 0.46 * x.cos(),
+                WindowFunction::BlackmanHarris =>
+                    0.35875 - 0.48829 * x.cos() + 0.14128 * (2.0 * x).cos() - 0.01168 * (3.0 * x).cos(),
+            };
+        }
+
+        output
+    }
+}
+
+// Computes amplitude spectra of fixed size windows. The FFT plan and all buffers are created
+// once, so analyzing a window allocates nothing.
+pub struct SpectrumAnalyzer {
+    fft: Arc<dyn FFT<f32>>,
+    window: Vec<f32>,
+    window_gain: f32,
+    frequency_bins: Vec<f32>,
+
+    input: Vec<Complex<f32>>,
+    output: Vec<Complex<f32>>,
+    amplitudes: Vec<f32>,
+}
+
+impl SpectrumAnalyzer {
+    pub fn new(sample_rate: u32, window_size: usize, window_function: WindowFunction) -> SpectrumAnalyzer {
+        let mut planner = FFTplanner::new(false);
+        let window = window_function.coefficients(window_size);
+        // Dividing by the sum of the window keeps a sine wave at the same amplitude no
+        // matter which window function is used.
+        let window_gain = window.iter().sum::<f32>();
+
+        SpectrumAnalyzer {
+            fft: planner.plan_fft(window_size),
+            window,
+            window_gain,
+            frequency_bins: frequency_bins(sample_rate, window_size as u32),
+
+            input: vec![Complex::zero(); window_size],
+            output: vec![Complex::zero(); window_size],
+            amplitudes: vec![0.0; window_size / 2],
+        }
+    }
+
+    // The frequency of each amplitude, in Hz.
+    pub fn frequency_bins(&self) -> &[f32] {
+        &self.frequency_bins
+    }
+
+    // The amplitude of each frequency bin up to the Nyquist frequency, as of the last window.
+    pub fn amplitudes(&self) -> &[f32] {
+        &self.amplitudes
+    }
+
+    pub fn process(&mut self, samples: &[f32]) {
+        for ((input, &sample), &weight) in self.input.iter_mut().zip(samples).zip(&self.window) {
+            *input = Complex::new(sample * weight, 0.0);
+        }
+
+        self.fft.process(&mut self.input, &mut self.output);
+
+        for i in 0..self.amplitudes.len() {
+            let re = self.output[i].re;
+            let im = self.output[i].im;
+            self.amplitudes[i] = (re * re + im * im).sqrt() / self.window_gain;
+        }
+    }
+
+    // Multiply every amplitude of the last window by the gain.
+    pub fn apply_gain(&mut self, gain: f32) {
+        for amplitude in self.amplitudes.iter_mut() {
+            *amplitude *= gain;
+        }
+    }
+}
@@ -0,0 +1,180 @@
+use std::collections::VecDeque;
+
+// == Tempo Notes ==
+
+// Onset strength autocorrelation
+// Reference: Ellis, "Beat Tracking by Dynamic Programming" (2007), section 3.1.
+// Onsets recur at the beat period, so the autocorrelation of the onset strength envelope
+// peaks at lags of one beat (and at multiples of it). Weighting the autocorrelation with a
+// log-Gaussian around a preferred tempo picks the most plausible of those multiples.
+
+// Beat phase
+// Between onsets the phase advances at the estimated tempo. Each onset pulls the phase
+// towards the nearest beat, so the phase locks onto the actual beats. Off-beat onsets pull
+// equally in both directions and cancel out.
+
+const MIN_BPM: f32 = 60.0;
+const MAX_BPM: f32 = 200.0;
+const PREFERRED_BPM: f32 = 120.0;
+const PREFERRED_BPM_WIDTH_OCTAVES: f32 = 1.0;
+
+const HISTORY_SECONDS: f32 = 8.0;
+const MIN_HISTORY_SECONDS: f32 = 3.0;
+
+// How far the estimate moves towards each new measurement that agrees with it.
+const BPM_SMOOTHING: f32 = 0.1;
+const CONFIDENCE_SMOOTHING: f32 = 0.1;
+
+// How much of the distance to the nearest beat each onset corrects.
+const PHASE_CORRECTION: f32 = 0.3;
+
+pub struct TempoTracker {
+    frames_per_second: f32,
+    onset_history: VecDeque<f32>,
+    history_len: usize,
+
+    bpm: f32,
+    confidence: f32,
+    beat_phase: f32,
+}
+
+impl TempoTracker {
+    pub fn new(frames_per_second: f32) -> TempoTracker {
+        let history_len = (HISTORY_SECONDS * frames_per_second).ceil() as usize;
+
+        TempoTracker {
+            frames_per_second,
+            onset_history: VecDeque::with_capacity(history_len),
+            history_len,
+
+            bpm: 0.0,
+            confidence: 0.0,
+            beat_phase: 0.0,
+        }
+    }
+
+    pub fn bpm(&self) -> f32 {
+        self.bpm
+    }
+
+    // How strongly the onsets repeat at the estimated tempo, from 0 to 1.
+    pub fn confidence(&self) -> f32 {
+        self.confidence
+    }
+
+    // How far through the current beat we are, from 0 (on the beat) to 1.
+    pub fn beat_phase(&self) -> f32 {
+        self.beat_phase
+    }
+
+    // Add the onset strength of the latest frame, re-estimate the tempo and advance the
+    // beat phase.
+    pub fn update(&mut self, onset_strength: f32, is_onset: bool) {
+        self.update_beat_phase(is_onset);
+
+        self.onset_history.push_back(onset_strength);
+        if self.onset_history.len() > self.history_len {
+            self.onset_history.pop_front();
+        }
+
+        if (self.onset_history.len() as f32) < MIN_HISTORY_SECONDS * self.frames_per_second {
+            return;
+        }
+
+        let (bpm, confidence) = match self.estimate() {
+            Some(x) => x,
+            None => (self.bpm, 0.0),
+        };
+
+        if self.bpm == 0.0 {
+            self.bpm = bpm;
+        } else if (bpm - self.bpm).abs() / self.bpm < 0.1 {
+            self.bpm += (bpm - self.bpm) * BPM_SMOOTHING;
+        } else if confidence > self.confidence {
+            // A different tempo that is more convincing than the current one, e.g. a new song.
+            self.bpm = bpm;
+        }
+        self.confidence += (confidence - self.confidence) * CONFIDENCE_SMOOTHING;
+    }
+
+    fn update_beat_phase(&mut self, is_onset: bool) {
+        if self.bpm > 0.0 {
+            self.beat_phase += self.bpm / 60.0 / self.frames_per_second;
+            self.beat_phase -= self.beat_phase.floor();
+        }
+
+        if !is_onset {
+            return;
+        }
+
+        if self.bpm == 0.0 {
+            self.beat_phase = 0.0;
+            return;
+        }
+
+        // Signed distance to the nearest beat, in beats.
+        let error = if self.beat_phase < 0.5 { self.beat_phase } else { self.beat_phase - 1.0 };
+        self.beat_phase -= error * PHASE_CORRECTION;
+        self.beat_phase -= self.beat_phase.floor();
+    }
+
+    fn estimate(&self) -> Option<(f32, f32)> {
+        let history_len = self.onset_history.len();
+        let mean = self.onset_history.iter().sum::<f32>() / history_len as f32;
+        let onsets: Vec<f32> = self.onset_history.iter().map(|x| x - mean).collect();
+
+        let energy: f32 = onsets.iter().map(|x| x * x).sum();
+        if energy <= 1e-9 {
+            return None;
+        }
+
+        let min_lag = usize::max(2, (60.0 * self.frames_per_second / MAX_BPM).floor() as usize);
+        let max_lag = usize::min(history_len / 2, (60.0 * self.frames_per_second / MIN_BPM).ceil() as usize);
+        if min_lag >= max_lag {
+            return None;
+        }
+
+        // Normalized autocorrelation, with one extra lag on each side for interpolation.
+        let mut autocorrelation = vec![0.0; max_lag + 2];
+        for lag in (min_lag - 1)..(max_lag + 2) {
+            let mut sum = 0.0;
+            for i in lag..history_len {
+                sum += onsets[i] * onsets[i - lag];
+            }
+            // Compensate for fewer overlapping samples at longer lags.
+            autocorrelation[lag] = sum / energy * history_len as f32 / (history_len - lag) as f32;
+        }
+
+        let mut best_lag = 0;
+        let mut best_score = 0.0;
+        for (lag, &correlation) in autocorrelation.iter().enumerate().take(max_lag + 1).skip(min_lag) {
+            let bpm = 60.0 * self.frames_per_second / lag as f32;
+            let octaves = (bpm / PREFERRED_BPM).log2() / PREFERRED_BPM_WIDTH_OCTAVES;
+            let score = correlation * (-0.5 * octaves * octaves).exp();
+            if score > best_score {
+                best_score = score;
+                best_lag = lag;
+            }
+        }
+
+        if best_lag == 0 {
+            return None;
+        }
+
+        // Fit a parabola through the peak and its neighbours for a fractional lag.
+        let before = autocorrelation[best_lag - 1];
+        let peak = autocorrelation[best_lag];
+        let after = autocorrelation[best_lag + 1];
+        let curvature = before - 2.0 * peak + after;
+        let offset = if curvature < 0.0 {
+            (0.5 * (before - after) / curvature).clamp(-0.5, 0.5)
+        } else {
+            0.0
+        };
+
+        let bpm = 60.0 * self.frames_per_second / (best_lag as f32 + offset);
+        let confidence = peak.clamp(0.0, 1.0);
+
+        Some((bpm, confidence))
+    }
+}
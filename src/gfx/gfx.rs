@@ -1,10 +1,12 @@
 use std::os::raw::c_void;
-use std::sync::mpsc;
+use std::thread;
+use std::time;
 
 use glutin;
 use glutin::GlContext;
 
 use audio;
+use listen;
 use visualizer;
 use screen;
 
@@ -13,6 +15,9 @@ pub mod gl {
     include!(concat!(env!("OUT_DIR"), "/gl_bindings.rs"));
 }
 
+// How often to report audio frames the renderer was too slow to show.
+const DROPPED_FRAME_REPORT_INTERVAL: time::Duration = time::Duration::from_secs(10);
+
 macro_rules! gl_try {
     ($gl:expr; $call:expr) => {{
         let result = $call;
@@ -27,21 +32,25 @@ macro_rules! gl_try {
     }}
 }
 
+// Draws `fps` frames per second, each from the latest audio frame. Waits for the first
+// audio frame, then keeps drawing the last one it got if the audio stops.
 pub fn run(visualizer: visualizer::Visualizer,
            screen: Box<dyn screen::Screen>,
-           audio_rx: mpsc::Receiver<audio::AudioFrame>,
-           size: i32) {
+           audio_rx: listen::MailboxReceiver<audio::AudioFrame>,
+           size: i32,
+           fps: f32) {
     if screen.uses_window() {
-        render_with_window(visualizer, screen, audio_rx, size);
+        render_with_window(visualizer, screen, audio_rx, size, fps);
     } else {
-        render_without_window(visualizer, screen, audio_rx, size);
+        render_without_window(visualizer, screen, audio_rx, size, fps);
     }
 }
 
 fn render_with_window(visualizer: visualizer::Visualizer,
                       screen: Box<dyn screen::Screen>,
-                      audio_rx: mpsc::Receiver<audio::AudioFrame>,
-                      size: i32) {
+                      audio_rx: listen::MailboxReceiver<audio::AudioFrame>,
+                      size: i32,
+                      fps: f32) {
     let mut events_loop = glutin::EventsLoop::new();
     let window = glutin::WindowBuilder::new()
         .with_title("Music Visualizer")
@@ -50,12 +59,17 @@ fn render_with_window(visualizer: visualizer::Visualizer,
     let gl_window = glutin::GlWindow::new(window, context, &events_loop).unwrap();
     let mut pipeline = GfxPipeline::new(load_gl_window_as_context(&gl_window), visualizer, screen, size);
 
+    let mut latest_frame = match LatestFrame::new(&audio_rx) {
+        Some(x) => x,
+        None => return,
+    };
+    let mut render_clock = RenderClock::new(fps);
+    let mut dropped_frame_report = DroppedFrameReport::new();
+
     let mut running = true;
     while running {
-        let audio_frame = match audio_rx.recv() {
-            Ok(x) => x,
-            Err(_) => continue,
-        };
+        let audio_frame = latest_frame.next(&audio_rx);
+        dropped_frame_report.update(audio_rx.stats());
 
         events_loop.poll_events(|event| match event {
             glutin::Event::WindowEvent { event, .. } => match event {
@@ -68,13 +82,15 @@ fn render_with_window(visualizer: visualizer::Visualizer,
 
         pipeline.update(audio_frame);
         gl_window.swap_buffers().unwrap();
+        render_clock.wait();
     }
 }
 
 fn render_without_window(visualizer: visualizer::Visualizer,
                          screen: Box<dyn screen::Screen>,
-                         audio_rx: mpsc::Receiver<audio::AudioFrame>,
-                         size: i32) {
+                         audio_rx: listen::MailboxReceiver<audio::AudioFrame>,
+                         size: i32,
+                         fps: f32) {
     let window = glutin::WindowBuilder::new()
         .with_title("Music Visualizer")
         .with_visibility(false);
@@ -84,13 +100,101 @@ fn render_without_window(visualizer: visualizer::Visualizer,
     let mut pipeline = GfxPipeline::new(load_gl_window_as_context(&gl_window),
                                         visualizer, screen, size);
 
+    let mut latest_frame = match LatestFrame::new(&audio_rx) {
+        Some(x) => x,
+        None => return,
+    };
+    let mut render_clock = RenderClock::new(fps);
+    let mut dropped_frame_report = DroppedFrameReport::new();
+
     loop {
-        let audio_frame = match audio_rx.recv() {
-            Ok(x) => x,
-            Err(_) => continue,
-        };
+        let audio_frame = latest_frame.next(&audio_rx);
+        dropped_frame_report.update(audio_rx.stats());
 
         pipeline.update(audio_frame);
+        render_clock.wait();
+    }
+}
+
+// Holds on to the most recent audio frame, so there is always something to draw.
+struct LatestFrame {
+    audio_frame: audio::AudioFrame,
+}
+
+impl LatestFrame {
+    // Waits for the first audio frame, or returns None if the audio ends before it.
+    fn new(audio_rx: &listen::MailboxReceiver<audio::AudioFrame>) -> Option<LatestFrame> {
+        audio_rx.recv().map(|audio_frame| LatestFrame { audio_frame })
+    }
+
+    // The newest audio frame, or the last one again if nothing new has arrived.
+    fn next(&mut self, audio_rx: &listen::MailboxReceiver<audio::AudioFrame>) -> audio::AudioFrame {
+        match audio_rx.try_recv() {
+            Some(x) => self.audio_frame = x,
+            // A beat happens once, not once per redraw.
+            None => self.audio_frame.is_beat = false,
+        }
+
+        self.audio_frame.clone()
+    }
+}
+
+// Paces the render loop at a fixed frame rate.
+struct RenderClock {
+    frame_duration: time::Duration,
+    next_frame: time::Instant,
+}
+
+impl RenderClock {
+    fn new(fps: f32) -> RenderClock {
+        RenderClock {
+            frame_duration: time::Duration::from_nanos((1_000_000_000.0 / fps) as u64),
+            next_frame: time::Instant::now(),
+        }
+    }
+
+    // Sleep until it's time to draw the next frame.
+    fn wait(&mut self) {
+        self.next_frame += self.frame_duration;
+
+        let now = time::Instant::now();
+        if self.next_frame > now {
+            thread::sleep(self.next_frame - now);
+        } else {
+            // Running behind. Start counting again from now, rather than rushing to catch up.
+            self.next_frame = now;
+        }
+    }
+}
+
+// Prints how many audio frames were skipped since the last report, if any.
+struct DroppedFrameReport {
+    last_report: time::Instant,
+    last_stats: listen::MailboxStats,
+}
+
+impl DroppedFrameReport {
+    fn new() -> DroppedFrameReport {
+        DroppedFrameReport {
+            last_report: time::Instant::now(),
+            last_stats: listen::MailboxStats::default(),
+        }
+    }
+
+    fn update(&mut self, stats: listen::MailboxStats) {
+        if self.last_report.elapsed() < DROPPED_FRAME_REPORT_INTERVAL {
+            return;
+        }
+
+        let sent = stats.sent - self.last_stats.sent;
+        let dropped = stats.dropped - self.last_stats.dropped;
+        if dropped > 0 {
+            println!("Skipped {} of {} audio frames in the last {} seconds.",
+                     dropped, sent, self.last_report.elapsed().as_secs());
+        }
+
+        self.last_report = time::Instant::now();
+        self.last_stats = stats;
     }
 }
 
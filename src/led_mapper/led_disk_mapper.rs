@@ -6,7 +6,7 @@ use gfx::gl;
 
 // For use with DotStar RGB LED Disk - 240mm
 // https://adafruit.com/product/2477
-static RINGS: [[u8; 2]; 10] = [
+static RINGS: [[u8; 2]; NUM_RINGS] = [
     [254, 254],
     [248, 253],
     [236, 247],
@@ -20,6 +20,7 @@ static RINGS: [[u8; 2]; 10] = [
 ];
 
 pub const NUM_PIXELS: usize = 255;
+pub const NUM_RINGS: usize = 10;
 // Distance units based on normalized point space of [-1, 1].
 pub const DISTANCE_BETWEEN_RINGS: f32 = 0.1;
 pub const PIXEL_RADIUS: f32 = 0.035;
@@ -0,0 +1,158 @@
+use audio;
+use listen::listener::{Listener, ListenerConfig};
+use listen::wav::WavFile;
+use std::io;
+use std::io::Write;
+
+// == Analyze Notes ==
+
+// Offline analysis
+// Runs a whole file through the same `Listener` the visualizer uses, as fast as it can,
+// and writes one row per frame. With the same options as a live run, the rows match what
+// the visualizers would have received, so thresholds can be tuned by plotting them.
+
+// Timing
+// Each row is stamped with the time of the end of its window, which is when the frame
+// would have reached the visualizers during playback.
+
+// Stereo
+// With `stereo` set, each row also has the left and right channel's bands, after the mixed
+// bands.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OutputFormat {
+    // A header row, then one row per frame with a column per band.
+    Csv,
+    // An array with one object per frame, one per line. Only the JSON has the waveform.
+    Json,
+}
+
+impl OutputFormat {
+    pub fn from_name(name: &str) -> Option<OutputFormat> {
+        match name {
+            "csv" => Some(OutputFormat::Csv),
+            "json" => Some(OutputFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+// Analyze every window of a WAV file and write the frames to `output`. Analyzes the left
+// and right channels separately when `stereo` is set, like `WavSource`. Returns the number
+// of frames written.
+pub fn analyze_wav(wav: &WavFile, stereo: bool, config: ListenerConfig, format: OutputFormat,
+    output: &mut dyn Write) -> io::Result<usize> {
+    let (samples, channels) = if stereo {
+        (wav.to_stereo(), 2)
+    } else {
+        (wav.to_mono(), 1)
+    };
+
+    let window_len = config.window_sample_size * channels;
+    let hop_len = config.hop_size * channels;
+    let mut listener = Listener::new(wav.sample_rate, channels, config);
+    let band_centers = listener.band_centers().to_vec();
+
+    match format {
+        OutputFormat::Csv => write_csv_header(output, &band_centers, stereo)?,
+        OutputFormat::Json => output.write_all(b"[\n")?,
+    }
+
+    let mut frame_count = 0;
+    let mut start = 0;
+    while start + window_len <= samples.len() {
+        let frame = listener.analyze(&samples[start..start + window_len]);
+        let seconds = ((start + window_len) / channels) as f32 / wav.sample_rate as f32;
+
+        match format {
+            OutputFormat::Csv => write_csv_row(output, seconds, &frame, stereo)?,
+            OutputFormat::Json => {
+                if frame_count > 0 {
+                    output.write_all(b",\n")?;
+                }
+                write_json_object(output, seconds, &frame, stereo)?;
+            }
+        }
+
+        frame_count += 1;
+        start += hop_len;
+    }
+
+    if format == OutputFormat::Json {
+        output.write_all(b"\n]\n")?;
+    }
+    output.flush()?;
+
+    Ok(frame_count)
+}
+
+fn write_csv_header(output: &mut dyn Write, band_centers: &[f32], stereo: bool) -> io::Result<()> {
+    write!(output, "time,bpm,bpm_confidence,beat_phase,is_beat,onset_strength,silent,\
+                    low_power,mid_power,high_power,\
+                    smoothed_low_power,smoothed_mid_power,smoothed_high_power,rms,peak")?;
+    for center in band_centers {
+        write!(output, ",band_{}hz", center.round())?;
+    }
+    if stereo {
+        for center in band_centers {
+            write!(output, ",left_band_{}hz", center.round())?;
+        }
+        for center in band_centers {
+            write!(output, ",right_band_{}hz", center.round())?;
+        }
+    }
+    writeln!(output)
+}
+
+fn write_csv_row(output: &mut dyn Write, seconds: f32, frame: &audio::AudioFrame, stereo: bool)
+    -> io::Result<()> {
+    write!(output, "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+           seconds, frame.bpm, frame.bpm_confidence, frame.beat_phase,
+           frame.is_beat as u8, frame.onset_strength, frame.silent as u8,
+           frame.low_power, frame.mid_power, frame.high_power,
+           frame.smoothed_low_power, frame.smoothed_mid_power, frame.smoothed_high_power,
+           frame.rms, frame.peak)?;
+    for band in &frame.bands {
+        write!(output, ",{}", band)?;
+    }
+    if stereo {
+        for band in frame.left_bands.iter().chain(&frame.right_bands) {
+            write!(output, ",{}", band)?;
+        }
+    }
+    writeln!(output)
+}
+
+fn write_json_object(output: &mut dyn Write, seconds: f32, frame: &audio::AudioFrame, stereo: bool)
+    -> io::Result<()> {
+    let json_array = |values: &[f32]| values.iter().map(|&x| json_number(x)).collect::<Vec<String>>().join(",");
+    let bands = json_array(&frame.bands);
+    let waveform = json_array(&frame.waveform);
+    let stereo_bands = if stereo {
+        format!(",\"left_bands\":[{}],\"right_bands\":[{}]",
+                json_array(&frame.left_bands), json_array(&frame.right_bands))
+    } else {
+        String::new()
+    };
+
+    write!(output, "{{\"time\":{},\"bpm\":{},\"bpm_confidence\":{},\"beat_phase\":{},\
+                    \"is_beat\":{},\"onset_strength\":{},\"silent\":{},\
+                    \"low_power\":{},\"mid_power\":{},\"high_power\":{},\
+                    \"smoothed_low_power\":{},\"smoothed_mid_power\":{},\"smoothed_high_power\":{},\
+                    \"rms\":{},\"peak\":{},\"bands\":[{}]{},\"waveform\":[{}]}}",
+           json_number(seconds), json_number(frame.bpm), json_number(frame.bpm_confidence),
+           json_number(frame.beat_phase), frame.is_beat, json_number(frame.onset_strength),
+           frame.silent, json_number(frame.low_power), json_number(frame.mid_power),
+           json_number(frame.high_power), json_number(frame.smoothed_low_power),
+           json_number(frame.smoothed_mid_power), json_number(frame.smoothed_high_power),
+           json_number(frame.rms), json_number(frame.peak), bands, stereo_bands, waveform)
+}
+
+// JSON has no NaN or infinity.
+fn json_number(value: f32) -> String {
+    if value.is_finite() {
+        value.to_string()
+    } else {
+        "null".to_string()
+    }
+}
@@ -1,137 +1,359 @@
 use audio;
-use sphinxad_sys::{ad_open_sps, ad_read, ad_start_rec};
-use rand::prelude::*;
-use std::sync::mpsc;
+use listen::mailbox::Sink;
+use listen::source::AudioSource;
 use std::thread;
 use std::time;
 
-pub fn visualize_microphone(tx: mpsc::SyncSender<audio::AudioFrame>,
-    samples_per_second: u32, window_sample_size: usize, amplitude_scalar: f32) {
-    println!("Connecting to microphone.");
-    let ad = unsafe { ad_open_sps(samples_per_second) };
-    let rec_successful = unsafe { ad_start_rec(ad) } == 0;
-    if !rec_successful {
-        panic!("Could not start recording microphone.");
-    }
-    println!("Connected microphone.");
+#[derive(Clone)]
+pub struct ListenerConfig {
+    pub window_sample_size: usize,
+    // Number of samples between the starts of consecutive windows.
+    pub hop_size: usize,
+    pub window_function: audio::WindowFunction,
+    pub gain_config: audio::GainConfig,
+    pub band_config: audio::BandConfig,
+    pub envelope_config: audio::EnvelopeConfig,
+    // Audio quieter than this, in dB relative to full scale, counts as silence.
+    pub silence_threshold_db: f32,
+}
 
-    let mut window: Vec<f32> = Vec::with_capacity(window_sample_size);
-    let duration_seconds = window_sample_size as f32 / samples_per_second as f32;
+// Pauses shorter than this, like the gaps between drum hits, don't count as silence.
+const MIN_SILENCE_SECONDS: f32 = 0.5;
 
-    let mut buffer = vec![0; samples_per_second as usize];
-    let raw_buffer = buffer.as_mut_ptr();
+// Pull samples from the source, split them into overlapping windows and send the analysis
+// of each window. Returns once the source runs out of samples.
+pub fn listen(mut source: Box<dyn AudioSource>, tx: Box<dyn Sink<audio::AudioFrame> + Send>,
+    config: ListenerConfig) {
+    let channels = source.channels();
+    let window_len = config.window_sample_size * channels;
+    let hop_len = config.hop_size * channels;
+    let mut listener = Listener::new(source.sample_rate(), channels, config);
+    let mut window: Vec<f32> = Vec::with_capacity(window_len * 2);
 
-    let mut average_amplitudes = vec![0.0; 0];  // Store the average amplitudes over the last 800 samples.
-    let mut amplitude_scalar = amplitude_scalar;
     loop {
-        let sample_count = unsafe { ad_read(ad, raw_buffer, samples_per_second) };
-        if sample_count != 0 {
-            for i in 0..sample_count as usize {
-                let sample_value = buffer[i] as f32 / i16::max_value() as f32;
-                let clamped_value = f32::max(-1.0, f32::min(1.0, sample_value));
-                window.push(clamped_value);
-            }
+        if !source.read(&mut window) {
+            return;
         }
 
-        if window.len() < window_sample_size {
+        if window.len() < window_len {
             thread::sleep(time::Duration::from_millis(5));
             continue;
         }
 
-        let average_amplitude = visualize_samples(&window[0..window_sample_size].to_vec(), duration_seconds, amplitude_scalar, &tx);
-        average_amplitudes.push(average_amplitude);
-        if average_amplitudes.len() > 400 {
-            average_amplitudes.drain(0..1);
-            let avg: f32 = average_amplitudes.iter().sum::<f32>() / average_amplitudes.len() as f32;
-            amplitude_scalar = 0.01 / avg;
-        }
+        while window.len() >= window_len {
+            let audio_frame = listener.analyze(&window[0..window_len]);
+            tx.send(audio_frame);
 
-        window = window.split_off(window_sample_size);
+            window.drain(0..hop_len);
+        }
     }
 }
 
-pub fn visualize_fake(tx: mpsc::SyncSender<audio::AudioFrame>) {
-	let mut rng = thread_rng();
+// Turns windows of samples into audio frames. Holds all state that carries over from
+// one window to the next.
+pub struct Listener {
+    window_sample_size: usize,
+    channels: usize,
+    mono_window: Vec<f32>,
+    spectrum_analyzer: audio::SpectrumAnalyzer,
+    // The spectrum at the fixed starting gain, for analysis that shouldn't react to the
+    // automatic gain changing or the noise gate opening.
+    fixed_gain: f32,
+    fixed_gain_amplitudes: Vec<f32>,
 
-	loop {
-		let bpm = 0.0;
+    // Only used for stereo sources.
+    left_window: Vec<f32>,
+    right_window: Vec<f32>,
+    left_spectrum_analyzer: audio::SpectrumAnalyzer,
+    right_spectrum_analyzer: audio::SpectrumAnalyzer,
+    hundred_hz_bucket_count: usize,
+    band_config: audio::BandConfig,
+    band_layout: audio::BandLayout,
 
-		let low_power = rng.gen();
-		let mid_power = rng.gen();
-		let high_power = rng.gen();
+    gain_control: audio::AutomaticGainControl,
 
-		let mut hundred_hz_buckets = [0.0; 200];
-		for i in 0..hundred_hz_buckets.len() {
-			hundred_hz_buckets[i] = rng.gen();
-		}
+    power_envelope: audio::EnvelopeFollower,
+    bucket_envelope: audio::EnvelopeFollower,
+    band_envelope: audio::EnvelopeFollower,
 
-		let audio_frame = audio::AudioFrame {
-			bpm,
+    seconds_per_frame: f32,
+    silence_threshold_db: f32,
+    idle_seconds: f32,
 
-			low_power,
-			mid_power,
-			high_power,
+    key_estimator: audio::KeyEstimator,
+    pitch_detector: audio::PitchDetector,
+    onset_detector: audio::OnsetDetector,
+    tempo_tracker: audio::TempoTracker,
+}
 
-			hundred_hz_buckets,
-		};
+impl Listener {
+    // `channels` is 1 for mono or 2 for interleaved stereo.
+    pub fn new(samples_per_second: u32, channels: usize, config: ListenerConfig) -> Listener {
+        let frames_per_second = samples_per_second as f32 / config.hop_size as f32;
+        let stereo_window_sample_size = if channels == 2 { config.window_sample_size } else { 0 };
 
-		tx.send(audio_frame).unwrap();
+        Listener {
+            window_sample_size: config.window_sample_size,
+            channels,
+            mono_window: vec![0.0; config.window_sample_size],
+            spectrum_analyzer: audio::SpectrumAnalyzer::new(
+                samples_per_second, config.window_sample_size, config.window_function),
+            fixed_gain: config.gain_config.initial_gain,
+            fixed_gain_amplitudes: Vec::with_capacity(config.window_sample_size / 2),
 
-		thread::sleep(time::Duration::from_millis(50));
-	}
-}
+            left_window: vec![0.0; stereo_window_sample_size],
+            right_window: vec![0.0; stereo_window_sample_size],
+            left_spectrum_analyzer: audio::SpectrumAnalyzer::new(
+                samples_per_second, stereo_window_sample_size, config.window_function),
+            right_spectrum_analyzer: audio::SpectrumAnalyzer::new(
+                samples_per_second, stereo_window_sample_size, config.window_function),
+            hundred_hz_bucket_count: audio::hundred_hz_bucket_count(samples_per_second),
+            band_layout: audio::BandLayout::new(&config.band_config, samples_per_second),
+            band_config: config.band_config,
 
-fn visualize_samples(samples: &Vec<f32>, duration_seconds: f32, amplitude_scalar: f32,
-    tx: &mpsc::SyncSender<audio::AudioFrame>) -> f32 {
-    let samples_per_second = (samples.len() as f32 / duration_seconds).ceil();
-    let frequency_bins = audio::frequency_bins(
-        samples_per_second as u32,
-        samples.len() as u32);
-
-    let fft_output = audio::compute_fft(samples.to_vec());
-    let amplitudes = audio::to_amplitude(fft_output, amplitude_scalar);
-
-    let low_threshold_hz = 1000.0;
-    let mid_threshold_hz = 4000.0;
-    let high_threshold_hz = 20000.0;
-
-    let mut low_power = 0.0;
-    let mut mid_power = 0.0;
-    let mut high_power = 0.0;
-
-    for i in 0..frequency_bins.len() {
-        if frequency_bins[i] <= low_threshold_hz {
-            low_power += amplitudes[i];
-        } else if frequency_bins[i] <= mid_threshold_hz {
-            mid_power += amplitudes[i];
-        } else if frequency_bins[i] <= high_threshold_hz {
-            high_power += amplitudes[i];
+            gain_control: audio::AutomaticGainControl::new(config.gain_config, frames_per_second),
+
+            power_envelope: audio::EnvelopeFollower::new(&config.envelope_config, frames_per_second),
+            bucket_envelope: audio::EnvelopeFollower::new(&config.envelope_config, frames_per_second),
+            band_envelope: audio::EnvelopeFollower::new(&config.envelope_config, frames_per_second),
+
+            seconds_per_frame: 1.0 / frames_per_second,
+            silence_threshold_db: config.silence_threshold_db,
+            idle_seconds: 0.0,
+
+            key_estimator: audio::KeyEstimator::new(frames_per_second),
+            pitch_detector: audio::PitchDetector::new(samples_per_second),
+            onset_detector: audio::OnsetDetector::new(frames_per_second),
+            tempo_tracker: audio::TempoTracker::new(frames_per_second),
         }
     }
 
-    low_power = f32::min(1.0, low_power);
-    mid_power = f32::min(1.0, mid_power);
-    high_power = f32::min(1.0, high_power);
+    // Center frequency of each band in the frames, in Hz.
+    pub fn band_centers(&self) -> &[f32] {
+        self.band_layout.centers()
+    }
+
+    // Analyze one window of interleaved samples.
+    pub fn analyze(&mut self, samples: &[f32]) -> audio::AudioFrame {
+        for i in 0..self.window_sample_size {
+            let frame = &samples[i * self.channels..(i + 1) * self.channels];
+            self.mono_window[i] = frame.iter().sum::<f32>() / self.channels as f32;
+        }
+
+        self.spectrum_analyzer.process(&self.mono_window);
+
+        let raw_amplitudes = self.spectrum_analyzer.amplitudes();
+        let level = raw_amplitudes.iter().sum::<f32>() / raw_amplitudes.len() as f32;
+        let rms = (self.mono_window.iter().map(|x| x * x).sum::<f32>() / self.window_sample_size as f32).sqrt();
+        self.gain_control.update(level, rms);
+
+        let fixed_gain = self.fixed_gain;
+        self.fixed_gain_amplitudes.clear();
+        self.fixed_gain_amplitudes.extend(raw_amplitudes.iter().map(|a| a * fixed_gain));
+        let fixed_gain_amplitudes = &self.fixed_gain_amplitudes;
+        self.spectrum_analyzer.apply_gain(self.gain_control.gain());
+
+        let rms_db = 20.0 * f32::max(rms, 1e-10).log10();
+        if rms_db < self.silence_threshold_db {
+            self.idle_seconds += self.seconds_per_frame;
+        } else {
+            self.idle_seconds = 0.0;
+        }
+        let idle_seconds = self.idle_seconds;
+        let silent = idle_seconds >= MIN_SILENCE_SECONDS;
+
+        let amplitudes = self.spectrum_analyzer.amplitudes();
+        let frequency_bins = self.spectrum_analyzer.frequency_bins();
+
+        let low_threshold_hz = self.band_config.low_threshold_hz;
+        let mid_threshold_hz = self.band_config.mid_threshold_hz;
+        let high_threshold_hz = self.band_config.high_threshold_hz;
+
+        let mut low_power = 0.0;
+        let mut mid_power = 0.0;
+        let mut high_power = 0.0;
+
+        for i in 0..frequency_bins.len() {
+            if frequency_bins[i] <= low_threshold_hz {
+                low_power += amplitudes[i];
+            } else if frequency_bins[i] <= mid_threshold_hz {
+                mid_power += amplitudes[i];
+            } else if frequency_bins[i] <= high_threshold_hz {
+                high_power += amplitudes[i];
+            }
+        }
+
+        low_power = f32::min(1.0, low_power);
+        mid_power = f32::min(1.0, mid_power);
+        high_power = f32::min(1.0, high_power);
+
+        let mut hundred_hz_buckets = vec![0.0; self.hundred_hz_bucket_count];
+        for i in 0..frequency_bins.len() {
+            let hundred_hz_bucket_index = (frequency_bins[i] / 100.0).floor() as usize;
+            hundred_hz_buckets[hundred_hz_bucket_index] += amplitudes[i];
+        }
+
+        self.power_envelope.update(&[low_power, mid_power, high_power]);
+        let smoothed_low_power = self.power_envelope.smoothed()[0];
+        let smoothed_mid_power = self.power_envelope.smoothed()[1];
+        let smoothed_high_power = self.power_envelope.smoothed()[2];
+
+        self.bucket_envelope.update(&hundred_hz_buckets);
+        let smoothed_buckets = self.bucket_envelope.smoothed().to_vec();
+        let peak_buckets = self.bucket_envelope.peaks().to_vec();
+
+        let spectral_centroid = audio::spectral_centroid(frequency_bins, fixed_gain_amplitudes);
+        let spectral_rolloff = audio::spectral_rolloff(frequency_bins, fixed_gain_amplitudes);
+        let spectral_flatness = audio::spectral_flatness(fixed_gain_amplitudes);
+        let zero_crossing_rate = audio::zero_crossing_rate(&self.mono_window);
+
+        let chroma = audio::chroma(frequency_bins, amplitudes);
+        self.key_estimator.update(&chroma);
+        let key = self.key_estimator.key();
+        let key_is_minor = self.key_estimator.is_minor();
+        let key_confidence = self.key_estimator.confidence();
+
+        // Pitch comes from the raw samples, since the period is all that matters.
+        if rms_db < self.silence_threshold_db {
+            self.pitch_detector.reset();
+        } else {
+            self.pitch_detector.update(&self.mono_window);
+        }
+        let pitch_hz = self.pitch_detector.pitch_hz();
+        let pitch_confidence = self.pitch_detector.confidence();
+        let (pitch_note, pitch_cents) = self.pitch_detector.note();
+
+        let mut bands = self.band_layout.compute(frequency_bins, amplitudes);
+        self.gain_control.normalize_bands(&mut bands);
+
+        self.band_envelope.update(&bands);
+        let smoothed_bands = self.band_envelope.smoothed().to_vec();
+        let peak_bands = self.band_envelope.peaks().to_vec();
+
+        // Onsets come from the spectrum at the fixed gain, so the gain changing or the gate
+        // opening doesn't look like a note starting.
+        self.onset_detector.update(fixed_gain_amplitudes);
+        let is_beat = self.onset_detector.is_onset();
+        let onset_strength = self.onset_detector.strength();
+        let spectral_flux = self.onset_detector.flux() / amplitudes.len() as f32;
+
+        self.tempo_tracker.update(self.onset_detector.flux(), is_beat);
+        let bpm = self.tempo_tracker.bpm();
+        let bpm_confidence = self.tempo_tracker.confidence();
+        let beat_phase = self.tempo_tracker.beat_phase();
+
+        let (left_bands, right_bands, balance, width) = if self.channels == 2 {
+            self.analyze_stereo(samples)
+        } else {
+            (bands.clone(), bands.clone(), 0.0, 0.0)
+        };
+
+        let waveform = audio::downsample(&self.mono_window, audio::WAVEFORM_LENGTH);
+        let (left_waveform, right_waveform) = if self.channels == 2 {
+            (audio::downsample(&self.left_window, audio::WAVEFORM_LENGTH),
+             audio::downsample(&self.right_window, audio::WAVEFORM_LENGTH))
+        } else {
+            (waveform.clone(), waveform.clone())
+        };
+        let peak = self.mono_window.iter().fold(0.0, |peak, x| f32::max(peak, x.abs()));
 
-    let mut hundred_hz_buckets = [0.0; 200];
-    for i in 0..frequency_bins.len() {
-        let hundred_hz_bucket_index = (frequency_bins[i] / 100.0).floor() as usize;
-        hundred_hz_buckets[hundred_hz_bucket_index] += amplitudes[i];
+        audio::AudioFrame {
+            bpm,
+            bpm_confidence,
+            beat_phase,
+            is_beat,
+            onset_strength,
+            silent,
+            idle_seconds,
+
+            low_power,
+            mid_power,
+            high_power,
+
+            hundred_hz_buckets,
+
+            smoothed_low_power,
+            smoothed_mid_power,
+            smoothed_high_power,
+            smoothed_buckets,
+            peak_buckets,
+
+            spectral_centroid,
+            spectral_flux,
+            spectral_rolloff,
+            spectral_flatness,
+            zero_crossing_rate,
+
+            chroma,
+            key,
+            key_is_minor,
+            key_confidence,
+
+            pitch_hz,
+            pitch_confidence,
+            pitch_note,
+            pitch_cents,
+
+            bands,
+            smoothed_bands,
+            peak_bands,
+
+            left_bands,
+            right_bands,
+            balance,
+            width,
+
+            waveform,
+            left_waveform,
+            right_waveform,
+            rms,
+            peak,
+        }
     }
 
-    let bpm = 0.0;
+    // Returns the left bands, the right bands, the balance and the width of a window of
+    // interleaved stereo samples.
+    fn analyze_stereo(&mut self, samples: &[f32]) -> (Vec<f32>, Vec<f32>, f32, f32) {
+        let mut mid_energy = 0.0;
+        let mut side_energy = 0.0;
+        for i in 0..self.window_sample_size {
+            let left = samples[i * 2];
+            let right = samples[i * 2 + 1];
+            self.left_window[i] = left;
+            self.right_window[i] = right;
 
-    let audio_frame = audio::AudioFrame {
-        bpm,
+            mid_energy += (left + right) * (left + right) / 4.0;
+            side_energy += (left - right) * (left - right) / 4.0;
+        }
+
+        // Both channels use the overall gain, so their levels stay comparable.
+        let gain = self.gain_control.gain();
+        self.left_spectrum_analyzer.process(&self.left_window);
+        self.left_spectrum_analyzer.apply_gain(gain);
+        self.right_spectrum_analyzer.process(&self.right_window);
+        self.right_spectrum_analyzer.apply_gain(gain);
 
-        low_power,
-        mid_power,
-        high_power,
+        let frequency_bins = self.spectrum_analyzer.frequency_bins();
+        let mut left_bands = self.band_layout.compute(frequency_bins, self.left_spectrum_analyzer.amplitudes());
+        let mut right_bands = self.band_layout.compute(frequency_bins, self.right_spectrum_analyzer.amplitudes());
+        // Corrected like the mixed bands, so mono and stereo sources give the same scale.
+        self.gain_control.correct_bands(&mut left_bands);
+        self.gain_control.correct_bands(&mut right_bands);
 
-        hundred_hz_buckets,
-    };
+        // -1 is all left, 1 is all right.
+        let left_energy: f32 = left_bands.iter().sum();
+        let right_energy: f32 = right_bands.iter().sum();
+        let balance = if left_energy + right_energy > 0.0 {
+            (right_energy - left_energy) / (right_energy + left_energy)
+        } else {
+            0.0
+        };
 
-    tx.send(audio_frame).unwrap();
+        // 0 is mono, around 0.5 is two unrelated channels and 1 is the channels out of phase.
+        let mid = mid_energy.sqrt();
+        let side = side_energy.sqrt();
+        let width = if mid + side > 0.0 { side / (mid + side) } else { 0.0 };
 
-    amplitudes.iter().sum::<f32>() / amplitudes.len() as f32
+        (left_bands, right_bands, balance, width)
+    }
 }
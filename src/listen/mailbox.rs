@@ -0,0 +1,192 @@
+use std::sync::{Arc, Condvar, Mutex};
+
+// == Mailbox Notes ==
+
+// Latest value
+// The audio thread must never wait for the renderer: while it waits, nobody reads the
+// capture device, and the device's buffer overflows. So instead of a queue, the threads
+// share a single slot. Sending replaces whatever is in the slot, and receiving takes it.
+// If the renderer is slow it skips straight to the freshest frame, and the frames it
+// never saw are counted as dropped.
+
+// Merging
+// Some values carry events that must not be skipped along with them, like a beat. When a
+// value replaces one that was never received, `merge(newer, older)` lets the newer value
+// carry those events on.
+
+pub fn mailbox<T>(merge: fn(&mut T, T)) -> (MailboxSender<T>, MailboxReceiver<T>) {
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            value: None,
+            sent: 0,
+            dropped: 0,
+            closed: false,
+        }),
+        available: Condvar::new(),
+        merge,
+    });
+
+    (MailboxSender { shared: shared.clone() }, MailboxReceiver { shared })
+}
+
+// Something a producer thread can hand values to without waiting for the consumer.
+pub trait Sink<T> {
+    fn send(&self, value: T);
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct MailboxStats {
+    // Every value ever sent.
+    pub sent: u64,
+    // Values replaced by a newer one before they were received.
+    pub dropped: u64,
+}
+
+struct Shared<T> {
+    state: Mutex<State<T>>,
+    available: Condvar,
+    merge: fn(&mut T, T),
+}
+
+struct State<T> {
+    value: Option<T>,
+    sent: u64,
+    dropped: u64,
+    // Set once the sender is gone.
+    closed: bool,
+}
+
+pub struct MailboxSender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> MailboxSender<T> {
+    // Never blocks for longer than it takes the receiver to take a value.
+    pub fn send(&self, mut value: T) {
+        let mut state = self.shared.state.lock().unwrap();
+        if let Some(older) = state.value.take() {
+            (self.shared.merge)(&mut value, older);
+            state.dropped += 1;
+        }
+        state.value = Some(value);
+        state.sent += 1;
+
+        self.shared.available.notify_one();
+    }
+}
+
+impl<T> Sink<T> for MailboxSender<T> {
+    fn send(&self, value: T) {
+        MailboxSender::send(self, value);
+    }
+}
+
+impl<T> Drop for MailboxSender<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.closed = true;
+
+        self.shared.available.notify_one();
+    }
+}
+
+pub struct MailboxReceiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> MailboxReceiver<T> {
+    // Wait for the next value. Returns None once the sender is gone and the last value
+    // has been received.
+    pub fn recv(&self) -> Option<T> {
+        let mut state = self.shared.state.lock().unwrap();
+        loop {
+            if let Some(value) = state.value.take() {
+                return Some(value);
+            }
+            if state.closed {
+                return None;
+            }
+            state = self.shared.available.wait(state).unwrap();
+        }
+    }
+
+    // Take the value if there is one, without waiting.
+    pub fn try_recv(&self) -> Option<T> {
+        self.shared.state.lock().unwrap().value.take()
+    }
+
+    pub fn stats(&self) -> MailboxStats {
+        let state = self.shared.state.lock().unwrap();
+        MailboxStats {
+            sent: state.sent,
+            dropped: state.dropped,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    fn keep_newer(_: &mut u32, _: u32) {}
+
+    fn add(newer: &mut u32, older: u32) {
+        *newer += older;
+    }
+
+    #[test]
+    fn receives_the_latest_value() {
+        let (tx, rx) = mailbox(keep_newer);
+        tx.send(1);
+        tx.send(2);
+        tx.send(3);
+
+        assert_eq!(rx.try_recv(), Some(3));
+        assert_eq!(rx.try_recv(), None);
+        let stats = rx.stats();
+        assert_eq!((stats.sent, stats.dropped), (3, 2));
+    }
+
+    #[test]
+    fn merges_values_that_were_never_received() {
+        let (tx, rx) = mailbox(add);
+        tx.send(1);
+        tx.send(2);
+        assert_eq!(rx.try_recv(), Some(3));
+
+        // Received values are not merged again.
+        tx.send(4);
+        assert_eq!(rx.try_recv(), Some(4));
+    }
+
+    #[test]
+    fn recv_ends_after_the_last_value() {
+        let (tx, rx) = mailbox(keep_newer);
+        tx.send(1);
+        drop(tx);
+
+        assert_eq!(rx.recv(), Some(1));
+        assert_eq!(rx.recv(), None);
+    }
+
+    #[test]
+    fn recv_waits_for_the_sender() {
+        let (tx, rx) = mailbox(add);
+        let sender = thread::spawn(move || {
+            for i in 1..=100 {
+                tx.send(i);
+            }
+        });
+
+        let mut total = 0;
+        while let Some(value) = rx.recv() {
+            total += value;
+        }
+        sender.join().unwrap();
+
+        // Every value is either received or merged into one that is.
+        assert_eq!(total, 5050);
+        assert_eq!(rx.stats().sent, 100);
+    }
+}
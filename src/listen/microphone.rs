@@ -0,0 +1,113 @@
+use listen::source::AudioSource;
+use sphinxad_sys::{ad_close, ad_open_dev, ad_open_sps, ad_read, ad_rec_t, ad_start_rec};
+use std::ffi::CString;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+
+pub struct MicrophoneSource {
+    ad: *const ad_rec_t,
+    samples_per_second: u32,
+    buffer: Vec<i16>,
+}
+
+// The recorder handle is created on the main thread, then owned and used only by the
+// listener thread.
+unsafe impl Send for MicrophoneSource {}
+
+impl MicrophoneSource {
+    // Open the named ALSA device, such as "plughw:2,0", or the default device when the
+    // name is empty.
+    pub fn open(device: &str, samples_per_second: u32) -> Result<MicrophoneSource, String> {
+        let device_description = if device.is_empty() { "default device" } else { device };
+        println!("Connecting to microphone ({}).", device_description);
+
+        let ad = if device.is_empty() {
+            unsafe { ad_open_sps(samples_per_second) }
+        } else {
+            let device_name = match CString::new(device) {
+                Ok(x) => x,
+                Err(_) => return Err(format!("Invalid device name: {}", device)),
+            };
+            unsafe { ad_open_dev(device_name.as_ptr(), samples_per_second) }
+        };
+        if ad.is_null() {
+            return Err(format!(
+                "Could not open microphone {} at {} samples per second. Run with --list_devices to see the available devices.",
+                device_description, samples_per_second));
+        }
+
+        let rec_successful = unsafe { ad_start_rec(ad) } == 0;
+        if !rec_successful {
+            unsafe { ad_close(ad) };
+            return Err(format!("Could not start recording microphone {}.", device_description));
+        }
+        println!("Connected microphone.");
+
+        Ok(MicrophoneSource {
+            ad,
+            samples_per_second,
+            buffer: vec![0; samples_per_second as usize],
+        })
+    }
+}
+
+impl Drop for MicrophoneSource {
+    fn drop(&mut self) {
+        unsafe { ad_close(self.ad) };
+    }
+}
+
+impl AudioSource for MicrophoneSource {
+    fn sample_rate(&self) -> u32 {
+        self.samples_per_second
+    }
+
+    fn read(&mut self, samples: &mut Vec<f32>) -> bool {
+        let sample_count = unsafe { ad_read(self.ad, self.buffer.as_mut_ptr(), self.samples_per_second) };
+        if sample_count < 0 {
+            // An error, such as a USB microphone being unplugged. Nothing more will come.
+            println!("Could not read microphone (sphinxad error {}).", sample_count);
+            return false;
+        }
+
+        for i in 0..sample_count as usize {
+            let sample_value = self.buffer[i] as f32 / i16::MAX as f32;
+            samples.push(sample_value.clamp(-1.0, 1.0));
+        }
+
+        true
+    }
+}
+
+// Returns the name and description of every ALSA capture device.
+//
+// sphinxad has no way to enumerate devices, so read them from the kernel instead. Each
+// line of /proc/asound/pcm looks like "02-00: USB Audio : USB Audio : capture 1".
+pub fn list_capture_devices() -> io::Result<Vec<(String, String)>> {
+    let mut pcm = String::new();
+    File::open("/proc/asound/pcm")?.read_to_string(&mut pcm)?;
+
+    let mut devices = Vec::new();
+    for line in pcm.lines() {
+        if !line.contains("capture") {
+            continue;
+        }
+
+        let fields: Vec<&str> = line.split(':').map(|field| field.trim()).collect();
+        let numbers: Vec<&str> = fields[0].split('-').collect();
+        if numbers.len() != 2 {
+            continue;
+        }
+        let card = numbers[0].parse::<u32>();
+        let device = numbers[1].parse::<u32>();
+        if let (Ok(card), Ok(device)) = (card, device) {
+            // plughw converts to whatever sample rate is asked for; hw only records natively.
+            let name = format!("plughw:{},{}", card, device);
+            let description = fields.get(1).cloned().unwrap_or("").to_string();
+            devices.push((name, description));
+        }
+    }
+
+    Ok(devices)
+}
@@ -1,3 +1,23 @@
+mod analyze;
 mod listener;
+mod mailbox;
+mod microphone;
+mod pcm;
+mod recording;
+mod sample_format;
+mod source;
+mod synth;
+mod udp;
+mod wav;
 
+pub use self::analyze::{analyze_wav, OutputFormat};
 pub use self::listener::*;
+pub use self::mailbox::{mailbox, MailboxReceiver, MailboxStats, Sink};
+pub use self::microphone::{list_capture_devices, MicrophoneSource};
+pub use self::pcm::{PcmFormat, PcmSource};
+pub use self::recording::{record_frames, replay_frames, FrameReader, FrameWriter};
+pub use self::sample_format::SampleFormat;
+pub use self::source::AudioSource;
+pub use self::synth::{DrumPattern, Signal, SynthConfig, SynthSource};
+pub use self::udp::{send_udp, UdpSource};
+pub use self::wav::{WavFile, WavSource};
@@ -0,0 +1,90 @@
+use listen::sample_format::SampleFormat;
+use listen::source::AudioSource;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+
+// == PCM Notes ==
+
+// Raw PCM
+// Headerless interleaved samples, as written by `arecord -t raw`, `ffmpeg -f s16le`, mpd's
+// fifo output or snapcast. Since there is no header, the format, rate and channel count
+// have to be given on the command line, and must match what the writer produces.
+
+// Pacing
+// Reads block until the writer has produced more samples, so the writer sets the pace.
+// Playing a file this way analyzes it as fast as it can be read.
+
+// Describes a stream of raw PCM samples.
+#[derive(Clone, Copy)]
+pub struct PcmFormat {
+    pub sample_format: SampleFormat,
+    pub sample_rate: u32,
+    pub channels: usize,
+}
+
+// Reads raw PCM from stdin or from a file or named pipe.
+pub struct PcmSource {
+    reader: Box<dyn Read + Send>,
+    format: PcmFormat,
+    // Channels handed to the listener: 2 for stereo, otherwise 1.
+    channels: usize,
+
+    // Bytes read but not yet decoded, at most one partial frame.
+    bytes: Vec<u8>,
+}
+
+impl PcmSource {
+    // A path of "-" reads stdin. Plays the first two channels as stereo when `stereo` is
+    // set, and mixes everything down to mono otherwise.
+    pub fn open(path: &str, format: PcmFormat, stereo: bool) -> io::Result<PcmSource> {
+        if format.channels == 0 || format.sample_rate == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "PCM input needs at least one channel and a sample rate"));
+        }
+
+        // Opening a named pipe waits until something starts writing to it.
+        let reader: Box<dyn Read + Send> = if path == "-" {
+            Box::new(io::stdin())
+        } else {
+            Box::new(File::open(path)?)
+        };
+
+        Ok(PcmSource {
+            reader,
+            format,
+            channels: if stereo && format.channels >= 2 { 2 } else { 1 },
+
+            bytes: Vec::new(),
+        })
+    }
+}
+
+impl AudioSource for PcmSource {
+    fn sample_rate(&self) -> u32 {
+        self.format.sample_rate
+    }
+
+    fn channels(&self) -> usize {
+        self.channels
+    }
+
+    fn read(&mut self, samples: &mut Vec<f32>) -> bool {
+        let mut chunk = [0; 4096];
+        match self.reader.read(&mut chunk) {
+            Ok(0) => return false,
+            Ok(count) => self.bytes.extend_from_slice(&chunk[0..count]),
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => return true,
+            Err(e) => {
+                println!("Could not read PCM input: {}", e);
+                return false;
+            }
+        }
+
+        let used = self.format.sample_format.decode_frames(
+            &self.bytes, self.format.channels, self.channels, samples);
+        self.bytes.drain(0..used);
+
+        true
+    }
+}
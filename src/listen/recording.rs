@@ -0,0 +1,630 @@
+use audio;
+use listen::mailbox::{MailboxSender, Sink};
+use std::fs::File;
+use std::io;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::sync::mpsc;
+use std::thread;
+use std::time;
+
+// == Recording Notes ==
+
+// Layout
+// A recording starts with an 8 byte magic string and a u32 format version, followed by one
+// record per frame: a u32 length, a u64 timestamp in microseconds since the recording
+// started, then every field of the frame in declaration order. Everything is little
+// endian. Floats are stored as their bits, bools as a byte, and vectors as a u32 length
+// followed by their values.
+
+// Versions
+// The layout follows `AudioFrame` exactly, so adding a field to it means adding it to
+// `encode_frame` and `decode_frame` and bumping FORMAT_VERSION. Old recordings are
+// rejected rather than misread.
+
+// Queueing
+// The renderer only ever wants the latest frame, but a recording needs all of them. So the
+// recorder passes each frame straight on to the renderer's mailbox, and queues a copy for
+// a thread of its own that writes it. A slow disk only makes the queue longer; it never
+// holds up the audio thread or costs the recording a frame.
+
+const MAGIC: &[u8; 8] = b"RPIVFRMS";
+const FORMAT_VERSION: u32 = 2;
+const HEADER_LEN: u64 = 12;
+// Far more than any frame needs, even with every bucket of a 384kHz stream. A longer record
+// means the file is corrupt, and trusting it could allocate up to 4GB.
+const MAX_RECORD_LEN: usize = 1 << 20;
+
+// Writes frames to a file, timestamped from the first frame.
+pub struct FrameWriter {
+    writer: BufWriter<File>,
+    start: Option<time::Instant>,
+    record: Vec<u8>,
+}
+
+impl FrameWriter {
+    pub fn create(path: &str) -> io::Result<FrameWriter> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(MAGIC)?;
+        writer.write_all(&u32_bytes(FORMAT_VERSION))?;
+
+        Ok(FrameWriter {
+            writer,
+            start: None,
+            record: Vec::new(),
+        })
+    }
+
+    // `time` is when the frame was made, which may be a while before it is written.
+    pub fn write(&mut self, frame: &audio::AudioFrame, time: time::Instant) -> io::Result<()> {
+        let start = *self.start.get_or_insert(time);
+        let elapsed = time.duration_since(start);
+        let timestamp = elapsed.as_secs() * 1_000_000 + elapsed.subsec_micros() as u64;
+
+        self.record.clear();
+        push_u64(&mut self.record, timestamp);
+        encode_frame(&mut self.record, frame);
+
+        self.writer.write_all(&u32_bytes(self.record.len() as u32))?;
+        self.writer.write_all(&self.record)?;
+        // Flush every frame, so a recording cut short by a crash or Ctrl-C is still usable.
+        self.writer.flush()
+    }
+}
+
+pub struct FrameReader {
+    reader: BufReader<File>,
+    record: Vec<u8>,
+}
+
+impl FrameReader {
+    pub fn open(path: &str) -> io::Result<FrameReader> {
+        let mut reader = BufReader::new(File::open(path)?);
+
+        let mut header = [0; HEADER_LEN as usize];
+        reader.read_exact(&mut header)
+            .map_err(|_| invalid_data("not a frame recording"))?;
+        if &header[0..8] != MAGIC {
+            return Err(invalid_data("not a frame recording"));
+        }
+        let version = read_u32(&header[8..12]);
+        if version != FORMAT_VERSION {
+            return Err(invalid_data(&format!(
+                "recording is format version {}, expected {}", version, FORMAT_VERSION)));
+        }
+
+        Ok(FrameReader {
+            reader,
+            record: Vec::new(),
+        })
+    }
+
+    // Go back to the first frame.
+    pub fn rewind(&mut self) -> io::Result<()> {
+        self.reader.seek(SeekFrom::Start(HEADER_LEN))?;
+        Ok(())
+    }
+
+    // The next frame and its timestamp in microseconds, or None at the end of the file.
+    // A record cut off by the end of the file also counts as the end.
+    pub fn read(&mut self) -> io::Result<Option<(u64, audio::AudioFrame)>> {
+        let mut length = [0; 4];
+        match self.reader.read_exact(&mut length) {
+            Ok(()) => (),
+            Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e),
+        }
+
+        let length = read_u32(&length) as usize;
+        if length > MAX_RECORD_LEN {
+            return Err(invalid_data(&format!("frame record of {} bytes is too long", length)));
+        }
+        self.record.resize(length, 0);
+        match self.reader.read_exact(&mut self.record) {
+            Ok(()) => (),
+            Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e),
+        }
+
+        let mut decoder = Decoder { bytes: &self.record, position: 0 };
+        let timestamp = decoder.u64()?;
+        let frame = decode_frame(&mut decoder)?;
+
+        Ok(Some((timestamp, frame)))
+    }
+}
+
+// Passes frames on to the renderer, and queues them for the recording thread.
+pub struct FrameRecorder {
+    tx: MailboxSender<audio::AudioFrame>,
+    queue: mpsc::Sender<(time::Instant, audio::AudioFrame)>,
+}
+
+impl Sink<audio::AudioFrame> for FrameRecorder {
+    fn send(&self, frame: audio::AudioFrame) {
+        // The recording thread is only gone once writing has failed.
+        let _ = self.queue.send((time::Instant::now(), frame.clone()));
+        self.tx.send(frame);
+    }
+}
+
+// Start recording every frame sent to the returned recorder, passing each one on to `tx`.
+// The returned thread finishes writing the queued frames once the recorder is dropped.
+pub fn record_frames(tx: MailboxSender<audio::AudioFrame>, mut writer: FrameWriter)
+    -> (FrameRecorder, thread::JoinHandle<()>) {
+    let (queue, rx) = mpsc::channel::<(time::Instant, audio::AudioFrame)>();
+
+    let handle = thread::spawn(move || {
+        for (time, frame) in rx {
+            if let Err(e) = writer.write(&frame, time) {
+                // Keep the show going, just without the recording.
+                println!("Stopped recording frames: {}", e);
+                return;
+            }
+        }
+    });
+
+    (FrameRecorder { tx, queue }, handle)
+}
+
+// Send the recorded frames with their original timing. Returns at the end of the
+// recording, unless `loop_playback` is set.
+pub fn replay_frames(mut reader: FrameReader, tx: Box<dyn Sink<audio::AudioFrame> + Send>,
+                     loop_playback: bool) -> io::Result<()> {
+    let mut start = time::Instant::now();
+    let mut first_timestamp = None;
+    let mut previous_timestamp = 0;
+
+    loop {
+        let (timestamp, frame) = match reader.read()? {
+            Some(x) => x,
+            None => {
+                if !loop_playback || first_timestamp.is_none() {
+                    return Ok(());
+                }
+                reader.rewind()?;
+                start = time::Instant::now();
+                first_timestamp = None;
+                previous_timestamp = 0;
+                continue;
+            }
+        };
+
+        if timestamp < previous_timestamp {
+            return Err(invalid_data(&format!(
+                "frame timestamps go backwards, from {} us to {} us", previous_timestamp, timestamp)));
+        }
+        previous_timestamp = timestamp;
+
+        let offset = timestamp.saturating_sub(*first_timestamp.get_or_insert(timestamp));
+        let due = start + time::Duration::from_micros(offset);
+        let now = time::Instant::now();
+        if due > now {
+            thread::sleep(due - now);
+        }
+
+        tx.send(frame);
+    }
+}
+
+fn encode_frame(out: &mut Vec<u8>, frame: &audio::AudioFrame) {
+    push_f32(out, frame.bpm);
+    push_f32(out, frame.bpm_confidence);
+    push_f32(out, frame.beat_phase);
+    push_bool(out, frame.is_beat);
+    push_f32(out, frame.onset_strength);
+    push_bool(out, frame.silent);
+    push_f32(out, frame.idle_seconds);
+
+    push_f32(out, frame.low_power);
+    push_f32(out, frame.mid_power);
+    push_f32(out, frame.high_power);
+    push_f32s(out, &frame.hundred_hz_buckets);
+
+    push_f32(out, frame.smoothed_low_power);
+    push_f32(out, frame.smoothed_mid_power);
+    push_f32(out, frame.smoothed_high_power);
+    push_f32s(out, &frame.smoothed_buckets);
+    push_f32s(out, &frame.peak_buckets);
+
+    push_f32(out, frame.spectral_centroid);
+    push_f32(out, frame.spectral_flux);
+    push_f32(out, frame.spectral_rolloff);
+    push_f32(out, frame.spectral_flatness);
+    push_f32(out, frame.zero_crossing_rate);
+
+    push_f32s(out, &frame.chroma);
+    push_u32(out, frame.key as u32);
+    push_bool(out, frame.key_is_minor);
+    push_f32(out, frame.key_confidence);
+
+    push_f32(out, frame.pitch_hz);
+    push_f32(out, frame.pitch_confidence);
+    push_u32(out, frame.pitch_note as u32);
+    push_f32(out, frame.pitch_cents);
+
+    push_f32s(out, &frame.bands);
+    push_f32s(out, &frame.smoothed_bands);
+    push_f32s(out, &frame.peak_bands);
+
+    push_f32s(out, &frame.left_bands);
+    push_f32s(out, &frame.right_bands);
+    push_f32(out, frame.balance);
+    push_f32(out, frame.width);
+
+    push_f32s(out, &frame.waveform);
+    push_f32s(out, &frame.left_waveform);
+    push_f32s(out, &frame.right_waveform);
+    push_f32(out, frame.rms);
+    push_f32(out, frame.peak);
+}
+
+fn decode_frame(decoder: &mut Decoder) -> io::Result<audio::AudioFrame> {
+    Ok(audio::AudioFrame {
+        bpm: decoder.f32()?,
+        bpm_confidence: decoder.f32()?,
+        beat_phase: decoder.f32()?,
+        is_beat: decoder.bool()?,
+        onset_strength: decoder.f32()?,
+        silent: decoder.bool()?,
+        idle_seconds: decoder.f32()?,
+
+        low_power: decoder.f32()?,
+        mid_power: decoder.f32()?,
+        high_power: decoder.f32()?,
+        hundred_hz_buckets: decoder.f32s()?,
+
+        smoothed_low_power: decoder.f32()?,
+        smoothed_mid_power: decoder.f32()?,
+        smoothed_high_power: decoder.f32()?,
+        smoothed_buckets: decoder.f32s()?,
+        peak_buckets: decoder.f32s()?,
+
+        spectral_centroid: decoder.f32()?,
+        spectral_flux: decoder.f32()?,
+        spectral_rolloff: decoder.f32()?,
+        spectral_flatness: decoder.f32()?,
+        zero_crossing_rate: decoder.f32()?,
+
+        chroma: {
+            let values = decoder.f32s()?;
+            if values.len() != 12 {
+                return Err(invalid_data("chroma must have 12 values"));
+            }
+            let mut chroma = [0.0; 12];
+            chroma.copy_from_slice(&values);
+            chroma
+        },
+        key: decoder.u32()? as usize,
+        key_is_minor: decoder.bool()?,
+        key_confidence: decoder.f32()?,
+
+        pitch_hz: decoder.f32()?,
+        pitch_confidence: decoder.f32()?,
+        pitch_note: decoder.u32()? as i32,
+        pitch_cents: decoder.f32()?,
+
+        bands: decoder.f32s()?,
+        smoothed_bands: decoder.f32s()?,
+        peak_bands: decoder.f32s()?,
+
+        left_bands: decoder.f32s()?,
+        right_bands: decoder.f32s()?,
+        balance: decoder.f32()?,
+        width: decoder.f32()?,
+
+        waveform: decoder.f32s()?,
+        left_waveform: decoder.f32s()?,
+        right_waveform: decoder.f32s()?,
+        rms: decoder.f32()?,
+        peak: decoder.f32()?,
+    })
+}
+
+fn push_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&u32_bytes(value));
+}
+
+fn push_u64(out: &mut Vec<u8>, value: u64) {
+    push_u32(out, value as u32);
+    push_u32(out, (value >> 32) as u32);
+}
+
+fn push_f32(out: &mut Vec<u8>, value: f32) {
+    push_u32(out, value.to_bits());
+}
+
+fn push_bool(out: &mut Vec<u8>, value: bool) {
+    out.push(value as u8);
+}
+
+fn push_f32s(out: &mut Vec<u8>, values: &[f32]) {
+    push_u32(out, values.len() as u32);
+    for &value in values {
+        push_f32(out, value);
+    }
+}
+
+fn u32_bytes(value: u32) -> [u8; 4] {
+    [value as u8, (value >> 8) as u8, (value >> 16) as u8, (value >> 24) as u8]
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    bytes[0] as u32 | (bytes[1] as u32) << 8 | (bytes[2] as u32) << 16 | (bytes[3] as u32) << 24
+}
+
+// Reads values back out of one record, failing instead of panicking on a short record.
+struct Decoder<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Decoder<'a> {
+    fn take(&mut self, count: usize) -> io::Result<&'a [u8]> {
+        if self.bytes.len() - self.position < count {
+            return Err(invalid_data("frame record is too short"));
+        }
+
+        let bytes = &self.bytes[self.position..self.position + count];
+        self.position += count;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        Ok(read_u32(self.take(4)?))
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        let low = self.u32()? as u64;
+        let high = self.u32()? as u64;
+        Ok(low | high << 32)
+    }
+
+    fn f32(&mut self) -> io::Result<f32> {
+        Ok(f32::from_bits(self.u32()?))
+    }
+
+    fn bool(&mut self) -> io::Result<bool> {
+        Ok(self.take(1)?[0] != 0)
+    }
+
+    fn f32s(&mut self) -> io::Result<Vec<f32>> {
+        let count = self.u32()? as usize;
+        if count > (self.bytes.len() - self.position) / 4 {
+            return Err(invalid_data("frame record is too short"));
+        }
+
+        let mut values = Vec::with_capacity(count);
+        for _ in 0..count {
+            values.push(self.f32()?);
+        }
+        Ok(values)
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use listen::mailbox::mailbox;
+    use std::env;
+    use std::fs;
+
+    fn test_frame() -> audio::AudioFrame {
+        let values = |start: f32, count: usize| (0..count).map(|i| start + i as f32 * 0.5).collect::<Vec<f32>>();
+
+        audio::AudioFrame {
+            bpm: 128.0,
+            bpm_confidence: 0.75,
+            beat_phase: 0.25,
+            is_beat: true,
+            onset_strength: 3.5,
+            silent: false,
+            idle_seconds: 0.0,
+
+            low_power: 1.0,
+            mid_power: 2.0,
+            high_power: 3.0,
+            hundred_hz_buckets: values(1.0, 120),
+
+            smoothed_low_power: 0.5,
+            smoothed_mid_power: 1.5,
+            smoothed_high_power: 2.5,
+            smoothed_buckets: values(2.0, 120),
+            peak_buckets: values(3.0, 120),
+
+            spectral_centroid: 1500.0,
+            spectral_flux: 0.125,
+            spectral_rolloff: 4000.0,
+            spectral_flatness: 0.0625,
+            zero_crossing_rate: 0.03125,
+
+            chroma: [1.0, 0.0, 0.5, 0.0, 0.75, 0.25, 0.0, 0.875, 0.0, 0.5, 0.0, 0.125],
+            key: 7,
+            key_is_minor: true,
+            key_confidence: 0.5,
+
+            pitch_hz: 440.0,
+            pitch_confidence: 0.9,
+            pitch_note: -3,
+            pitch_cents: -12.5,
+
+            bands: values(4.0, 16),
+            smoothed_bands: values(5.0, 16),
+            peak_bands: values(6.0, 16),
+
+            left_bands: values(7.0, 16),
+            right_bands: values(8.0, 16),
+            balance: -0.25,
+            width: 0.5,
+
+            waveform: values(-1.0, 256),
+            left_waveform: values(-0.5, 256),
+            right_waveform: values(0.0, 256),
+            rms: 0.3,
+            peak: f32::NAN,
+        }
+    }
+
+    fn encoded(frame: &audio::AudioFrame) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        encode_frame(&mut bytes, frame);
+        bytes
+    }
+
+    fn temp_path(name: &str) -> String {
+        env::temp_dir().join(format!("rpi-visualizer-{}-{}", name, std::process::id()))
+            .to_string_lossy().into_owned()
+    }
+
+    fn recording_bytes(version: u32, frames: &[(u64, audio::AudioFrame)]) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        push_u32(&mut bytes, version);
+        for &(timestamp, ref frame) in frames {
+            let mut record = Vec::new();
+            push_u64(&mut record, timestamp);
+            encode_frame(&mut record, frame);
+            push_u32(&mut bytes, record.len() as u32);
+            bytes.extend_from_slice(&record);
+        }
+        bytes
+    }
+
+    #[test]
+    fn frames_round_trip() {
+        let frame = test_frame();
+        let bytes = encoded(&frame);
+
+        let decoded = decode_frame(&mut Decoder { bytes: &bytes, position: 0 }).unwrap();
+        // Comparing the encodings compares every field, NaN included.
+        assert_eq!(encoded(&decoded), bytes);
+        assert_eq!(decoded.pitch_note, -3);
+        assert_eq!(decoded.key, 7);
+        assert!(decoded.is_beat && decoded.key_is_minor && !decoded.silent);
+    }
+
+    #[test]
+    fn short_records_are_errors() {
+        let bytes = encoded(&test_frame());
+        for &length in &[0, 1, 27, bytes.len() / 2, bytes.len() - 1] {
+            let result = decode_frame(&mut Decoder { bytes: &bytes[0..length], position: 0 });
+            assert_eq!(result.err().unwrap().kind(), io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn huge_vector_lengths_are_errors() {
+        let mut bytes = Vec::new();
+        push_u32(&mut bytes, u32::MAX);
+        assert!(Decoder { bytes: &bytes, position: 0 }.f32s().is_err());
+    }
+
+    #[test]
+    fn writer_and_reader_round_trip() {
+        let path = temp_path("round-trip");
+        let frame = test_frame();
+        let start = time::Instant::now();
+
+        let mut writer = FrameWriter::create(&path).unwrap();
+        writer.write(&frame, start).unwrap();
+        writer.write(&frame, start + time::Duration::from_millis(40)).unwrap();
+        drop(writer);
+
+        let mut reader = FrameReader::open(&path).unwrap();
+        let (first, decoded) = reader.read().unwrap().unwrap();
+        assert_eq!(first, 0);
+        assert_eq!(encoded(&decoded), encoded(&frame));
+        assert_eq!(reader.read().unwrap().unwrap().0, 40_000);
+        assert!(reader.read().unwrap().is_none());
+
+        reader.rewind().unwrap();
+        assert_eq!(reader.read().unwrap().unwrap().0, 0);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn truncated_recordings_end_early() {
+        let path = temp_path("truncated");
+        let mut bytes = recording_bytes(FORMAT_VERSION, &[(0, test_frame()), (1000, test_frame())]);
+        let length = bytes.len();
+        bytes.truncate(length - 10);
+        fs::write(&path, &bytes).unwrap();
+
+        let mut reader = FrameReader::open(&path).unwrap();
+        assert!(reader.read().unwrap().is_some());
+        assert!(reader.read().unwrap().is_none());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn huge_record_lengths_are_errors() {
+        let path = temp_path("huge-record");
+        let mut bytes = recording_bytes(FORMAT_VERSION, &[]);
+        push_u32(&mut bytes, u32::MAX);
+        bytes.extend_from_slice(&[0; 64]);
+        fs::write(&path, &bytes).unwrap();
+
+        let mut reader = FrameReader::open(&path).unwrap();
+        assert_eq!(reader.read().err().unwrap().kind(), io::ErrorKind::InvalidData);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn other_files_and_versions_are_rejected() {
+        let path = temp_path("rejected");
+
+        fs::write(&path, b"RIFF\0\0\0\0WAVEfmt ").unwrap();
+        assert_eq!(FrameReader::open(&path).err().unwrap().kind(), io::ErrorKind::InvalidData);
+
+        fs::write(&path, b"RPIV").unwrap();
+        assert_eq!(FrameReader::open(&path).err().unwrap().kind(), io::ErrorKind::InvalidData);
+
+        fs::write(&path, recording_bytes(FORMAT_VERSION - 1, &[])).unwrap();
+        assert_eq!(FrameReader::open(&path).err().unwrap().kind(), io::ErrorKind::InvalidData);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn replay_rejects_timestamps_going_backwards() {
+        let path = temp_path("backwards");
+        fs::write(&path, recording_bytes(FORMAT_VERSION, &[(5000, test_frame()), (1000, test_frame())])).unwrap();
+
+        let (tx, rx) = mailbox(audio::AudioFrame::merge_skipped);
+        let result = replay_frames(FrameReader::open(&path).unwrap(), Box::new(tx), false);
+        assert_eq!(result.err().unwrap().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(rx.stats().sent, 1);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn recorder_keeps_every_frame() {
+        let path = temp_path("recorder");
+        let (tx, rx) = mailbox(audio::AudioFrame::merge_skipped);
+        let (recorder, handle) = record_frames(tx, FrameWriter::create(&path).unwrap());
+
+        for _ in 0..50 {
+            recorder.send(test_frame());
+        }
+        drop(recorder);
+        handle.join().unwrap();
+
+        // The renderer only sees the latest frame, but the recording has them all.
+        assert!(rx.recv().is_some());
+        assert!(rx.recv().is_none());
+        let mut reader = FrameReader::open(&path).unwrap();
+        let mut count = 0;
+        while reader.read().unwrap().is_some() {
+            count += 1;
+        }
+        assert_eq!(count, 50);
+
+        fs::remove_file(&path).unwrap();
+    }
+}
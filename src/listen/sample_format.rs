@@ -0,0 +1,189 @@
+// == Sample Format Notes ==
+
+// Encodings
+// Reference: http://soundfile.sapp.org/doc/WaveFormat/
+// Uncompressed audio stores each sample as a little endian number. 8 bit samples are
+// unsigned, centered on 128. 16, 24 and 32 bit integer samples are signed, and 24 bit
+// samples are packed into 3 bytes. Floats are already in the range [-1, 1].
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SampleFormat {
+    U8,
+    S16,
+    S24,
+    S32,
+    F32,
+    F64,
+}
+
+impl SampleFormat {
+    // Accepts the names arecord (S16_LE, FLOAT_LE) and ffmpeg (s16le, f32le) use for little
+    // endian samples. arecord's S24_LE is padded to 4 bytes, so only S24_3LE is packed S24.
+    pub fn from_name(name: &str) -> Option<SampleFormat> {
+        match &name.to_lowercase()[..] {
+            "u8" => Some(SampleFormat::U8),
+            "s16" | "s16le" | "s16_le" => Some(SampleFormat::S16),
+            "s24" | "s24le" | "s24_3le" => Some(SampleFormat::S24),
+            "s32" | "s32le" | "s32_le" => Some(SampleFormat::S32),
+            "f32" | "f32le" | "float" | "float_le" => Some(SampleFormat::F32),
+            "f64" | "f64le" | "float64" | "float64_le" => Some(SampleFormat::F64),
+            _ => None,
+        }
+    }
+
+    pub fn bytes_per_sample(&self) -> usize {
+        match *self {
+            SampleFormat::U8 => 1,
+            SampleFormat::S16 => 2,
+            SampleFormat::S24 => 3,
+            SampleFormat::S32 => 4,
+            SampleFormat::F32 => 4,
+            SampleFormat::F64 => 8,
+        }
+    }
+
+    // Decode a single little endian sample into the range [-1, 1].
+    pub fn decode(&self, bytes: &[u8]) -> f32 {
+        let value = match *self {
+            SampleFormat::U8 => (bytes[0] as f32 - 128.0) / 128.0,
+            SampleFormat::S16 => {
+                let value = (bytes[0] as u16 | (bytes[1] as u16) << 8) as i16;
+                value as f32 / 32768.0
+            }
+            SampleFormat::S24 => {
+                // Shift into the top of an i32 so the sign bit is extended.
+                let value = ((bytes[0] as u32) << 8 |
+                             (bytes[1] as u32) << 16 |
+                             (bytes[2] as u32) << 24) as i32 >> 8;
+                value as f32 / 8388608.0
+            }
+            SampleFormat::S32 => {
+                let value = (bytes[0] as u32 |
+                             (bytes[1] as u32) << 8 |
+                             (bytes[2] as u32) << 16 |
+                             (bytes[3] as u32) << 24) as i32;
+                value as f32 / 2147483648.0
+            }
+            SampleFormat::F32 => {
+                let bits = bytes[0] as u32 |
+                           (bytes[1] as u32) << 8 |
+                           (bytes[2] as u32) << 16 |
+                           (bytes[3] as u32) << 24;
+                f32::from_bits(bits)
+            }
+            SampleFormat::F64 => {
+                let mut bits = 0u64;
+                for (i, &byte) in bytes[0..8].iter().enumerate() {
+                    bits |= (byte as u64) << (8 * i);
+                }
+                f64::from_bits(bits) as f32
+            }
+        };
+
+        // A NaN would poison the whole spectrum, so it plays as silence.
+        if value.is_nan() { 0.0 } else { value.clamp(-1.0, 1.0) }
+    }
+
+    // Decode whole frames of interleaved samples with `input_channels` channels, appending
+    // them to `samples` as stereo (the first two channels) when `output_channels` is 2, or
+    // as mono (all channels mixed) otherwise. Returns the number of bytes used; a partial
+    // frame at the end is left alone.
+    pub fn decode_frames(&self, bytes: &[u8], input_channels: usize, output_channels: usize,
+                         samples: &mut Vec<f32>) -> usize {
+        let bytes_per_sample = self.bytes_per_sample();
+        let bytes_per_frame = bytes_per_sample * input_channels;
+        let frame_count = bytes.len() / bytes_per_frame;
+
+        for frame in bytes[0..frame_count * bytes_per_frame].chunks(bytes_per_frame) {
+            let sample = |channel: usize| {
+                let start = channel * bytes_per_sample;
+                self.decode(&frame[start..start + bytes_per_sample])
+            };
+
+            if output_channels == 2 {
+                samples.push(sample(0));
+                samples.push(sample(usize::min(1, input_channels - 1)));
+            } else {
+                samples.push((0..input_channels).map(sample).sum::<f32>() /
+                             input_channels as f32);
+            }
+        }
+
+        frame_count * bytes_per_frame
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_name_accepts_arecord_and_ffmpeg_names() {
+        assert_eq!(SampleFormat::from_name("S16_LE"), Some(SampleFormat::S16));
+        assert_eq!(SampleFormat::from_name("s16le"), Some(SampleFormat::S16));
+        assert_eq!(SampleFormat::from_name("S24_3LE"), Some(SampleFormat::S24));
+        assert_eq!(SampleFormat::from_name("s24le"), Some(SampleFormat::S24));
+        assert_eq!(SampleFormat::from_name("S32_LE"), Some(SampleFormat::S32));
+        assert_eq!(SampleFormat::from_name("FLOAT_LE"), Some(SampleFormat::F32));
+        assert_eq!(SampleFormat::from_name("float"), Some(SampleFormat::F32));
+        assert_eq!(SampleFormat::from_name("f32le"), Some(SampleFormat::F32));
+        assert_eq!(SampleFormat::from_name("FLOAT64_LE"), Some(SampleFormat::F64));
+        assert_eq!(SampleFormat::from_name("float64"), Some(SampleFormat::F64));
+        assert_eq!(SampleFormat::from_name("U8"), Some(SampleFormat::U8));
+    }
+
+    #[test]
+    fn from_name_rejects_other_layouts() {
+        // Padded to 4 bytes, not packed.
+        assert_eq!(SampleFormat::from_name("S24_LE"), None);
+        assert_eq!(SampleFormat::from_name("s16be"), None);
+        assert_eq!(SampleFormat::from_name("le"), None);
+        assert_eq!(SampleFormat::from_name(""), None);
+    }
+
+    #[test]
+    fn decode_covers_full_scale() {
+        assert_eq!(SampleFormat::U8.decode(&[0]), -1.0);
+        assert_eq!(SampleFormat::U8.decode(&[128]), 0.0);
+        assert_eq!(SampleFormat::S16.decode(&[0x00, 0x80]), -1.0);
+        assert_eq!(SampleFormat::S16.decode(&[0x00, 0x40]), 0.5);
+        assert_eq!(SampleFormat::S24.decode(&[0x00, 0x00, 0x80]), -1.0);
+        assert_eq!(SampleFormat::S24.decode(&[0x00, 0x00, 0xc0]), -0.5);
+        assert_eq!(SampleFormat::S32.decode(&[0x00, 0x00, 0x00, 0x40]), 0.5);
+
+        let bytes = 0.25f32.to_bits().to_le_bytes();
+        assert_eq!(SampleFormat::F32.decode(&bytes), 0.25);
+        let bytes = (-0.75f64).to_bits().to_le_bytes();
+        assert_eq!(SampleFormat::F64.decode(&bytes), -0.75);
+    }
+
+    #[test]
+    fn decode_clamps_out_of_range_floats() {
+        assert_eq!(SampleFormat::F32.decode(&2.0f32.to_bits().to_le_bytes()), 1.0);
+        assert_eq!(SampleFormat::F32.decode(&(-2.0f32).to_bits().to_le_bytes()), -1.0);
+        assert_eq!(SampleFormat::F32.decode(&f32::INFINITY.to_bits().to_le_bytes()), 1.0);
+        assert_eq!(SampleFormat::F32.decode(&f32::NAN.to_bits().to_le_bytes()), 0.0);
+        assert_eq!(SampleFormat::F64.decode(&f64::NAN.to_bits().to_le_bytes()), 0.0);
+    }
+
+    #[test]
+    fn decode_frames_mixes_down_and_keeps_partial_frames() {
+        // Two stereo frames of s16, then half a frame.
+        let bytes = [0x00, 0x40, 0x00, 0xc0, 0x00, 0x40, 0x00, 0x40, 0x01];
+
+        let mut mono = Vec::new();
+        assert_eq!(SampleFormat::S16.decode_frames(&bytes, 2, 1, &mut mono), 8);
+        assert_eq!(mono, vec![0.0, 0.5]);
+
+        let mut stereo = Vec::new();
+        assert_eq!(SampleFormat::S16.decode_frames(&bytes, 2, 2, &mut stereo), 8);
+        assert_eq!(stereo, vec![0.5, -0.5, 0.5, 0.5]);
+    }
+
+    #[test]
+    fn decode_frames_copies_mono_to_both_channels() {
+        let mut stereo = Vec::new();
+        SampleFormat::S16.decode_frames(&[0x00, 0x40], 1, 2, &mut stereo);
+        assert_eq!(stereo, vec![0.5, 0.5]);
+    }
+}
@@ -0,0 +1,17 @@
+// A source of raw audio samples.
+//
+// Sources only acquire samples. Windowing, gain control and analysis all happen in the
+// listener, so every source is analyzed the same way.
+pub trait AudioSource: Send {
+    fn sample_rate(&self) -> u32;
+
+    // Number of interleaved channels the source reads, either 1 (mono) or 2 (stereo).
+    fn channels(&self) -> usize {
+        1
+    }
+
+    // Append any newly available samples, interleaved and in the range [-1, 1], to `samples`.
+    // Only whole frames (one sample per channel) are appended.
+    // Returns false once the source has run out of samples.
+    fn read(&mut self, samples: &mut Vec<f32>) -> bool;
+}
@@ -0,0 +1,411 @@
+use listen::source::AudioSource;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::f64::consts::PI;
+use std::time;
+
+// == Synth Notes ==
+
+// Test signals
+// Synthesizes audio in real time, so everything downstream (FFT, gain, beat tracking, ...)
+// sees something close to real music. All randomness comes from a seeded generator and
+// the signal only depends on the sample index, so the same seed always produces the same
+// audio.
+
+// Pink noise
+// Reference: http://www.firstpr.com.au/dsp/pink-noise/ (Paul Kellet's refined method)
+// White noise filtered so each octave has the same energy, like most music.
+
+// Drums
+// Reference: https://www.soundonsound.com/techniques/practical-bass-drum-synthesis
+// A kick is a sine wave whose pitch drops quickly, a snare is a burst of noise with a
+// short tone, and a hi-hat is a very short burst of high passed noise. Each plays on a 16
+// step (sixteenth note) pattern.
+
+// Chords
+// A I-V-vi-IV progression in C, one chord per bar, with a bass note an octave below.
+
+const SWEEP_SECONDS: f64 = 10.0;
+const SWEEP_MIN_HZ: f64 = 40.0;
+const SWEEP_MAX_HZ: f64 = 16000.0;
+
+const STEPS_PER_BEAT: usize = 4;
+const BEATS_PER_BAR: usize = 4;
+
+// MIDI notes of C, G, Am and F.
+static CHORDS: [[u8; 3]; 4] = [[60, 64, 67], [67, 71, 74], [69, 72, 76], [65, 69, 72]];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Signal {
+    Sweep,
+    PinkNoise,
+    Drums,
+    Chords,
+    // Drums and chords together.
+    Demo,
+}
+
+impl Signal {
+    pub fn from_name(name: &str) -> Option<Signal> {
+        match name {
+            "sweep" => Some(Signal::Sweep),
+            "pink_noise" => Some(Signal::PinkNoise),
+            "drums" => Some(Signal::Drums),
+            "chords" => Some(Signal::Chords),
+            "demo" => Some(Signal::Demo),
+            _ => None,
+        }
+    }
+}
+
+// Which steps each drum plays on.
+#[derive(Clone)]
+pub struct DrumPattern {
+    pub kick: Vec<bool>,
+    pub snare: Vec<bool>,
+    pub hat: Vec<bool>,
+}
+
+impl DrumPattern {
+    // Kick on every beat, snare on 2 and 4, and hats on every eighth note.
+    pub fn four_on_the_floor() -> DrumPattern {
+        DrumPattern::parse("x...x...x...x...,....x.......x...,x.x.x.x.x.x.x.x.").unwrap()
+    }
+
+    // Parses "kick,snare,hat", each a string of x (hit) and . (rest) per sixteenth note,
+    // e.g. "x...x...x...x...,....x.......x...,x.x.x.x.x.x.x.x.". All three must be the
+    // same length.
+    pub fn parse(pattern: &str) -> Option<DrumPattern> {
+        let parts: Vec<Vec<bool>> = pattern.split(',').map(|part| {
+            part.trim().chars().map(|c| c == 'x' || c == 'X').collect()
+        }).collect();
+
+        if parts.len() != 3 || parts[0].is_empty() ||
+            parts[1].len() != parts[0].len() || parts[2].len() != parts[0].len() {
+            return None;
+        }
+
+        Some(DrumPattern {
+            kick: parts[0].clone(),
+            snare: parts[1].clone(),
+            hat: parts[2].clone(),
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct SynthConfig {
+    pub signal: Signal,
+    pub sample_rate: u32,
+    pub bpm: f32,
+    pub pattern: DrumPattern,
+    pub seed: u64,
+}
+
+// Plays a synthesized test signal in real time, as if it were being recorded.
+pub struct SynthSource {
+    config: SynthConfig,
+    channels: usize,
+    rng: StdRng,
+
+    start: Option<time::Instant>,
+    played_frames: u64,
+
+    sweep_phase: f64,
+    pink: [f32; 7],
+
+    step: Option<usize>,
+    // Seconds since each drum was last hit.
+    since_kick: f64,
+    since_snare: f64,
+    since_hat: f64,
+    kick_phase: f64,
+    last_white: f32,
+}
+
+impl SynthSource {
+    // Plays in stereo when `stereo` is set, with the drums and chords spread out.
+    pub fn new(config: SynthConfig, stereo: bool) -> SynthSource {
+        let mut seed = [0; 32];
+        for (i, byte) in seed.iter_mut().take(8).enumerate() {
+            *byte = (config.seed >> (8 * i)) as u8;
+        }
+
+        SynthSource {
+            config,
+            channels: if stereo { 2 } else { 1 },
+            rng: StdRng::from_seed(seed),
+
+            start: None,
+            played_frames: 0,
+
+            sweep_phase: 0.0,
+            pink: [0.0; 7],
+
+            step: None,
+            since_kick: 1e3,
+            since_snare: 1e3,
+            since_hat: 1e3,
+            kick_phase: 0.0,
+            last_white: 0.0,
+        }
+    }
+
+    // The next sample as (left, right).
+    fn next_frame(&mut self) -> (f32, f32) {
+        let seconds = self.played_frames as f64 / self.config.sample_rate as f64;
+
+        match self.config.signal {
+            Signal::Sweep => {
+                let sample = self.sweep(seconds) * 0.5;
+                (sample, sample)
+            }
+            Signal::PinkNoise => {
+                let sample = self.pink_noise() * 0.5;
+                (sample, sample)
+            }
+            Signal::Drums => {
+                // A kick and snare together would go past full scale.
+                let drums = self.drums(seconds);
+                (drums.0 * 0.8, drums.1 * 0.8)
+            }
+            Signal::Chords => self.chords(seconds),
+            Signal::Demo => {
+                let drums = self.drums(seconds);
+                let chords = self.chords(seconds);
+                // Scaled down to stay within [-1, 1].
+                ((drums.0 + chords.0 * 0.6) * 0.7, (drums.1 + chords.1 * 0.6) * 0.7)
+            }
+        }
+    }
+
+    fn sweep(&mut self, seconds: f64) -> f32 {
+        let progress = (seconds % SWEEP_SECONDS) / SWEEP_SECONDS;
+        let frequency = SWEEP_MIN_HZ * (SWEEP_MAX_HZ / SWEEP_MIN_HZ).powf(progress);
+
+        self.sweep_phase = (self.sweep_phase + 2.0 * PI * frequency / self.config.sample_rate as f64) % (2.0 * PI);
+        self.sweep_phase.sin() as f32
+    }
+
+    fn pink_noise(&mut self) -> f32 {
+        let white = self.rng.gen::<f32>() * 2.0 - 1.0;
+
+        let pink = &mut self.pink;
+        pink[0] = 0.99886 * pink[0] + white * 0.0555179;
+        pink[1] = 0.99332 * pink[1] + white * 0.0750759;
+        pink[2] = 0.96900 * pink[2] + white * 0.153852;
+        pink[3] = 0.86650 * pink[3] + white * 0.3104856;
+        pink[4] = 0.55000 * pink[4] + white * 0.5329522;
+        pink[5] = -0.7616 * pink[5] - white * 0.0168980;
+        let output = pink[0] + pink[1] + pink[2] + pink[3] + pink[4] + pink[5] + pink[6] + white * 0.5362;
+        pink[6] = white * 0.115926;
+
+        output * 0.11
+    }
+
+    fn drums(&mut self, seconds: f64) -> (f32, f32) {
+        let seconds_per_step = 60.0 / self.config.bpm as f64 / STEPS_PER_BEAT as f64;
+        let step_count = self.config.pattern.kick.len();
+        let step = (seconds / seconds_per_step) as usize;
+        if self.step != Some(step) {
+            self.step = Some(step);
+            let index = step % step_count;
+            if self.config.pattern.kick[index] {
+                self.since_kick = 0.0;
+                self.kick_phase = 0.0;
+            }
+            if self.config.pattern.snare[index] {
+                self.since_snare = 0.0;
+            }
+            if self.config.pattern.hat[index] {
+                self.since_hat = 0.0;
+            }
+        }
+
+        let sample_seconds = 1.0 / self.config.sample_rate as f64;
+        let white = self.rng.gen::<f32>() * 2.0 - 1.0;
+
+        let kick_hz = 50.0 + 100.0 * (-self.since_kick / 0.03).exp();
+        self.kick_phase += 2.0 * PI * kick_hz * sample_seconds;
+        let kick = (self.kick_phase.sin() * (-self.since_kick / 0.25).exp()) as f32 * 0.8;
+
+        let snare_tone = (2.0 * PI * 190.0 * self.since_snare).sin() * (-self.since_snare / 0.08).exp();
+        let snare = white * (-self.since_snare / 0.12).exp() as f32 * 0.35 + snare_tone as f32 * 0.25;
+
+        // The difference between consecutive white noise samples leaves mostly highs.
+        let hat = (white - self.last_white) * (-self.since_hat / 0.03).exp() as f32 * 0.15;
+        self.last_white = white;
+
+        self.since_kick += sample_seconds;
+        self.since_snare += sample_seconds;
+        self.since_hat += sample_seconds;
+
+        // Kick in the middle, snare a little left, hats right.
+        (kick + snare * 0.6 + hat * 0.4, kick + snare * 0.4 + hat * 0.6)
+    }
+
+    fn chords(&mut self, seconds: f64) -> (f32, f32) {
+        let seconds_per_bar = 60.0 / self.config.bpm as f64 * BEATS_PER_BAR as f64;
+        let bar = (seconds / seconds_per_bar) as usize;
+        let chord = &CHORDS[bar % CHORDS.len()];
+
+        // Each chord swells in, then settles.
+        let since_chord = seconds - bar as f64 * seconds_per_bar;
+        let envelope = (1.0 - (-since_chord / 0.02).exp()) * (0.6 + 0.4 * (-since_chord / 0.5).exp());
+
+        let tone = |note: u8, seconds: f64| -> f64 {
+            let frequency = 440.0 * 2f64.powf((note as f64 - 69.0) / 12.0);
+            let phase = 2.0 * PI * frequency * seconds;
+            phase.sin() + 0.5 * (2.0 * phase).sin() + 0.25 * (3.0 * phase).sin()
+        };
+
+        let bass = tone(chord[0] - 24, seconds) * 0.3;
+        let mut left = bass;
+        let mut right = bass;
+        for (i, &note) in chord.iter().enumerate() {
+            // Spread the notes of the chord across the stereo field.
+            let pan = i as f64 / (chord.len() - 1) as f64;
+            let value = tone(note, seconds) * 0.12;
+            left += value * (1.0 - pan * 0.6);
+            right += value * (0.4 + pan * 0.6);
+        }
+
+        ((left * envelope) as f32, (right * envelope) as f32)
+    }
+}
+
+impl AudioSource for SynthSource {
+    fn sample_rate(&self) -> u32 {
+        self.config.sample_rate
+    }
+
+    fn channels(&self) -> usize {
+        self.channels
+    }
+
+    fn read(&mut self, samples: &mut Vec<f32>) -> bool {
+        let start = *self.start.get_or_insert_with(time::Instant::now);
+        let elapsed = start.elapsed();
+        let elapsed_nanos = elapsed.as_secs() * 1_000_000_000 + elapsed.subsec_nanos() as u64;
+        let due_frames = elapsed_nanos * self.config.sample_rate as u64 / 1_000_000_000;
+
+        while self.played_frames < due_frames {
+            let (left, right) = self.next_frame();
+            if self.channels == 2 {
+                samples.push(left);
+                samples.push(right);
+            } else {
+                samples.push((left + right) / 2.0);
+            }
+            self.played_frames += 1;
+        }
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(signal: Signal, seed: u64) -> SynthConfig {
+        SynthConfig {
+            signal,
+            sample_rate: 24000,
+            bpm: 120.0,
+            pattern: DrumPattern::four_on_the_floor(),
+            seed,
+        }
+    }
+
+    // The first `count` frames, without waiting for them to be due.
+    fn render(source: &mut SynthSource, count: usize) -> Vec<(f32, f32)> {
+        (0..count).map(|_| {
+            let frame = source.next_frame();
+            source.played_frames += 1;
+            frame
+        }).collect()
+    }
+
+    #[test]
+    fn parse_reads_each_drum() {
+        let pattern = DrumPattern::parse("x..X, .x.. ,..x.").unwrap();
+        assert_eq!(pattern.kick, vec![true, false, false, true]);
+        assert_eq!(pattern.snare, vec![false, true, false, false]);
+        assert_eq!(pattern.hat, vec![false, false, true, false]);
+    }
+
+    #[test]
+    fn parse_rejects_malformed_patterns() {
+        assert!(DrumPattern::parse("").is_none());
+        assert!(DrumPattern::parse(",,").is_none());
+        assert!(DrumPattern::parse("x...,x...").is_none());
+        assert!(DrumPattern::parse("x...,x...,x...,x...").is_none());
+        assert!(DrumPattern::parse("x...,x..,x...").is_none());
+    }
+
+    #[test]
+    fn four_on_the_floor_is_one_bar() {
+        let pattern = DrumPattern::four_on_the_floor();
+        assert_eq!(pattern.kick.len(), STEPS_PER_BEAT * BEATS_PER_BAR);
+        assert_eq!(pattern.kick.iter().filter(|&&x| x).count(), 4);
+        assert_eq!(pattern.snare.iter().filter(|&&x| x).count(), 2);
+        assert_eq!(pattern.hat.iter().filter(|&&x| x).count(), 8);
+    }
+
+    #[test]
+    fn signals_by_name() {
+        assert_eq!(Signal::from_name("sweep"), Some(Signal::Sweep));
+        assert_eq!(Signal::from_name("pink_noise"), Some(Signal::PinkNoise));
+        assert_eq!(Signal::from_name("drums"), Some(Signal::Drums));
+        assert_eq!(Signal::from_name("chords"), Some(Signal::Chords));
+        assert_eq!(Signal::from_name("demo"), Some(Signal::Demo));
+        assert_eq!(Signal::from_name("noise"), None);
+    }
+
+    #[test]
+    fn same_seed_same_audio() {
+        for &signal in &[Signal::Sweep, Signal::PinkNoise, Signal::Drums, Signal::Chords, Signal::Demo] {
+            let first = render(&mut SynthSource::new(config(signal, 7), true), 24000);
+            let second = render(&mut SynthSource::new(config(signal, 7), true), 24000);
+            assert!(first == second, "{:?} is not deterministic", signal);
+        }
+    }
+
+    #[test]
+    fn seed_changes_the_noise() {
+        let first = render(&mut SynthSource::new(config(Signal::PinkNoise, 1), false), 1000);
+        let second = render(&mut SynthSource::new(config(Signal::PinkNoise, 2), false), 1000);
+        assert!(first != second);
+    }
+
+    #[test]
+    fn signals_stay_in_range() {
+        for &signal in &[Signal::Sweep, Signal::PinkNoise, Signal::Drums, Signal::Chords, Signal::Demo] {
+            // Two bars at 120 BPM.
+            for (left, right) in render(&mut SynthSource::new(config(signal, 3), true), 96000) {
+                assert!(left.abs() <= 1.0 && right.abs() <= 1.0, "{:?} clips", signal);
+            }
+        }
+    }
+
+    #[test]
+    fn kicks_land_on_the_beat() {
+        let mut pattern = DrumPattern::four_on_the_floor();
+        pattern.snare = vec![false; pattern.kick.len()];
+        pattern.hat = vec![false; pattern.kick.len()];
+        let mut config = config(Signal::Drums, 0);
+        config.pattern = pattern;
+
+        // At 120 BPM a beat is 12000 samples. The kick is loud just after each beat and has
+        // mostly faded away just before the next.
+        let frames = render(&mut SynthSource::new(config, false), 48000);
+        let level = |range: std::ops::Range<usize>| {
+            frames[range].iter().map(|x| x.0.abs()).fold(0.0, f32::max)
+        };
+        for beat in 1..4 {
+            assert!(level(beat * 12000..beat * 12000 + 600) > 0.5);
+            assert!(level(beat * 12000 - 600..beat * 12000) < 0.15);
+        }
+    }
+}
@@ -0,0 +1,640 @@
+use listen::sample_format::SampleFormat;
+use listen::source::AudioSource;
+use std::collections::BTreeMap;
+use std::io;
+use std::net::UdpSocket;
+use std::thread;
+use std::time;
+
+// == UDP Notes ==
+
+// Packets
+// Each datagram carries one block of interleaved samples behind a 14 byte header, all
+// little endian: "RPIA", a u32 sequence number (counting up from 0 for each new stream),
+// the u32 sample rate, a u8 sample format and the u8 channel count. The sender built into
+// this program (--udp_send) writes 256 frames of 16 bit samples per packet, which fits in
+// a single ethernet frame even for stereo.
+
+// Jitter buffer
+// Packets can arrive late, in bursts or out of order. Playback waits until a little audio
+// has been buffered, then plays it at the stream's sample rate by the local clock, putting
+// packets back in sequence order as it goes. The buffer absorbs the jitter at the cost of
+// that much latency. If the sender's clock runs fast the buffer slowly grows, so the oldest
+// audio is dropped once it holds far more than it should.
+
+// Loss concealment
+// A packet that still hasn't arrived when it's due is replaced by the previous packet,
+// faded, so a lost packet doesn't punch a click into the spectrum. Longer gaps, such as the
+// sender pausing, fade out to silence and playback starts over from the buffering step
+// once packets return.
+
+const MAGIC: &[u8; 4] = b"RPIA";
+const HEADER_LEN: usize = 14;
+const MAX_PACKET_LEN: usize = 65507;
+
+// Frames per packet written by `send_udp`.
+const FRAMES_PER_PACKET: usize = 256;
+// Each concealed packet is this much quieter than the one before.
+const CONCEALMENT_FADE: f32 = 0.5;
+// After concealing this many packets in a row, assume the sender stopped.
+const MAX_CONCEALED_PACKETS: u32 = 8;
+// A sequence number this far behind the expected one means the sender restarted.
+const MAX_REORDER: u32 = 1000;
+// Drop audio once the buffer holds this many times the jitter buffer size.
+const MAX_BUFFER_FACTOR: usize = 3;
+// How often to report lost packets.
+const LOSS_REPORT_INTERVAL: time::Duration = time::Duration::from_secs(10);
+
+struct PacketHeader {
+    sequence: u32,
+    sample_rate: u32,
+    format: SampleFormat,
+    channels: usize,
+}
+
+// Plays a stream of PCM packets from the network, as sent by `send_udp`.
+pub struct UdpSource {
+    socket: UdpSocket,
+    receive_buffer: Vec<u8>,
+
+    sample_rate: u32,
+    format: SampleFormat,
+    input_channels: usize,
+    // Channels handed to the listener: 2 for stereo, otherwise 1.
+    channels: usize,
+
+    // Decoded packets waiting to be played, by sequence number.
+    packets: BTreeMap<u32, Vec<f32>>,
+    // Frames to buffer before playback starts.
+    jitter_frames: usize,
+
+    // None while buffering.
+    playout_start: Option<time::Instant>,
+    played_frames: u64,
+    next_sequence: u32,
+    last_packet: Vec<f32>,
+    concealed_packets: u32,
+
+    lost_packets: u64,
+    last_loss_report: time::Instant,
+}
+
+impl UdpSource {
+    // Listens on `address`, which may be just a port. Waits for the first packet, since
+    // it says what the sample rate and channels are. Plays the first two channels as
+    // stereo when `stereo` is set, and mixes everything down to mono otherwise.
+    pub fn bind(address: &str, jitter_seconds: f32, stereo: bool) -> io::Result<UdpSource> {
+        let address = if address.contains(':') {
+            address.to_string()
+        } else {
+            format!("0.0.0.0:{}", address)
+        };
+        let socket = UdpSocket::bind(&address)?;
+        println!("Waiting for audio on udp {}", address);
+
+        let mut receive_buffer = vec![0; MAX_PACKET_LEN];
+        let (length, header) = loop {
+            let length = socket.recv(&mut receive_buffer)?;
+            if let Some(header) = decode_header(&receive_buffer[0..length]) {
+                break (length, header);
+            }
+        };
+        socket.set_nonblocking(true)?;
+
+        let input_channels = header.channels;
+        let mut source = UdpSource {
+            socket,
+            receive_buffer,
+
+            sample_rate: header.sample_rate,
+            format: header.format,
+            input_channels,
+            channels: if stereo && input_channels >= 2 { 2 } else { 1 },
+
+            packets: BTreeMap::new(),
+            // Less than a packet would drop every packet as soon as it arrives.
+            jitter_frames: usize::max(FRAMES_PER_PACKET, (jitter_seconds * header.sample_rate as f32) as usize),
+
+            playout_start: None,
+            played_frames: 0,
+            next_sequence: header.sequence,
+            last_packet: Vec::new(),
+            concealed_packets: 0,
+
+            lost_packets: 0,
+            last_loss_report: time::Instant::now(),
+        };
+
+        let first_packet = source.receive_buffer[0..length].to_vec();
+        source.accept(&first_packet);
+
+        Ok(source)
+    }
+
+    // Move every packet waiting on the socket into the jitter buffer.
+    fn receive(&mut self) {
+        loop {
+            let length = match self.socket.recv(&mut self.receive_buffer) {
+                Ok(x) => x,
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return,
+                Err(e) => {
+                    println!("Could not receive UDP audio: {}", e);
+                    return;
+                }
+            };
+
+            let packet = self.receive_buffer[0..length].to_vec();
+            self.accept(&packet);
+        }
+    }
+
+    fn accept(&mut self, packet: &[u8]) {
+        let header = match decode_header(packet) {
+            Some(x) => x,
+            None => return,
+        };
+        if header.sample_rate != self.sample_rate || header.format != self.format ||
+            header.channels != self.input_channels {
+            // The listener can't change sample rate or channels part way through.
+            return;
+        }
+
+        // Check for a pause first: a sender that restarted while we were concealing the
+        // gap starts again from sequence 0, which would otherwise look too late.
+        if self.concealed_packets > MAX_CONCEALED_PACKETS {
+            // The sender is back after a pause. Buffer up again before playing.
+            self.packets.clear();
+            self.playout_start = None;
+            self.concealed_packets = 0;
+            self.next_sequence = header.sequence;
+        }
+
+        let behind = self.next_sequence.wrapping_sub(header.sequence);
+        if behind > MAX_REORDER && behind <= u32::MAX / 2 {
+            // The sender restarted, so start over.
+            self.packets.clear();
+            self.playout_start = None;
+            self.next_sequence = header.sequence;
+        } else if behind != 0 && behind <= MAX_REORDER {
+            // Too late, its slot has already been played or concealed.
+            return;
+        }
+
+        let mut samples = Vec::new();
+        self.format.decode_frames(&packet[HEADER_LEN..], self.input_channels, self.channels, &mut samples);
+        self.packets.insert(header.sequence, samples);
+
+        // Drop the oldest audio if the sender's clock is running ahead of ours.
+        while self.buffered_frames() > self.jitter_frames * MAX_BUFFER_FACTOR {
+            let oldest = *self.packets.keys().next().unwrap();
+            self.packets.remove(&oldest);
+            self.next_sequence = oldest.wrapping_add(1);
+        }
+    }
+
+    fn buffered_frames(&self) -> usize {
+        self.packets.values().map(|x| x.len()).sum::<usize>() / self.channels
+    }
+
+    // Play the next packet, or conceal it if it's missing. Returns the frames played.
+    fn play_next(&mut self, samples: &mut Vec<f32>) -> usize {
+        if let Some(packet) = self.packets.remove(&self.next_sequence) {
+            samples.extend_from_slice(&packet);
+            self.last_packet = packet;
+            self.next_sequence = self.next_sequence.wrapping_add(1);
+            self.concealed_packets = 0;
+            return self.last_packet.len() / self.channels;
+        }
+
+        // Skip over long gaps, rather than concealing them one packet at a time.
+        if let Some(&first) = self.packets.keys().next() {
+            let gap = first.wrapping_sub(self.next_sequence);
+            if gap > MAX_CONCEALED_PACKETS {
+                self.lost_packets += gap as u64;
+                self.next_sequence = first;
+                return 0;
+            }
+        }
+
+        if self.last_packet.is_empty() {
+            self.last_packet = vec![0.0; FRAMES_PER_PACKET * self.channels];
+        }
+        for sample in self.last_packet.iter_mut() {
+            *sample *= CONCEALMENT_FADE;
+        }
+        samples.extend_from_slice(&self.last_packet);
+
+        // Once the sender has stopped, keep playing silence without running ahead of it.
+        if self.concealed_packets <= MAX_CONCEALED_PACKETS {
+            self.lost_packets += 1;
+            self.next_sequence = self.next_sequence.wrapping_add(1);
+            self.concealed_packets += 1;
+        }
+
+        self.last_packet.len() / self.channels
+    }
+}
+
+impl AudioSource for UdpSource {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn channels(&self) -> usize {
+        self.channels
+    }
+
+    fn read(&mut self, samples: &mut Vec<f32>) -> bool {
+        self.receive();
+
+        let start = match self.playout_start {
+            Some(x) => x,
+            None => {
+                if self.buffered_frames() < self.jitter_frames || self.packets.is_empty() {
+                    return true;
+                }
+
+                self.next_sequence = *self.packets.keys().next().unwrap();
+                self.played_frames = 0;
+                *self.playout_start.get_or_insert_with(time::Instant::now)
+            }
+        };
+
+        let elapsed = start.elapsed();
+        let elapsed_nanos = elapsed.as_secs() * 1_000_000_000 + elapsed.subsec_nanos() as u64;
+        let due_frames = elapsed_nanos * self.sample_rate as u64 / 1_000_000_000;
+
+        while self.played_frames < due_frames {
+            self.played_frames += self.play_next(samples) as u64;
+        }
+
+        if self.last_loss_report.elapsed() >= LOSS_REPORT_INTERVAL {
+            if self.lost_packets > 0 {
+                println!("Concealed {} lost UDP audio packets in the last {} seconds.",
+                         self.lost_packets, self.last_loss_report.elapsed().as_secs());
+            }
+            self.lost_packets = 0;
+            self.last_loss_report = time::Instant::now();
+        }
+
+        true
+    }
+}
+
+// Send audio from `source` to a `UdpSource` at `address` as 16 bit packets. Returns once
+// the source runs out of samples.
+pub fn send_udp(mut source: Box<dyn AudioSource>, address: &str) -> io::Result<()> {
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.connect(address)?;
+
+    let sample_rate = source.sample_rate();
+    let channels = source.channels();
+    let packet_len = FRAMES_PER_PACKET * channels;
+
+    let mut samples = Vec::new();
+    let mut packet = Vec::with_capacity(HEADER_LEN + packet_len * 2);
+    let mut sequence: u32 = 0;
+
+    loop {
+        if !source.read(&mut samples) {
+            return Ok(());
+        }
+
+        if samples.len() < packet_len {
+            thread::sleep(time::Duration::from_millis(2));
+            continue;
+        }
+
+        while samples.len() >= packet_len {
+            packet.clear();
+            encode_packet(&mut packet, sequence, sample_rate, channels, &samples[0..packet_len]);
+
+            match socket.send(&packet) {
+                Ok(_) => (),
+                // Nobody is listening yet. Keep sending so they can join at any time.
+                Err(ref e) if e.kind() == io::ErrorKind::ConnectionRefused => (),
+                Err(e) => return Err(e),
+            }
+
+            samples.drain(0..packet_len);
+            sequence = sequence.wrapping_add(1);
+        }
+    }
+}
+
+// Append a packet of interleaved `samples` as 16 bit PCM.
+fn encode_packet(packet: &mut Vec<u8>, sequence: u32, sample_rate: u32, channels: usize,
+                 samples: &[f32]) {
+    encode_header(packet, &PacketHeader {
+        sequence,
+        sample_rate,
+        format: SampleFormat::S16,
+        channels,
+    });
+    for &sample in samples {
+        let value = (sample.clamp(-1.0, 1.0) * 32767.0) as i16;
+        packet.push(value as u8);
+        packet.push((value >> 8) as u8);
+    }
+}
+
+fn encode_header(packet: &mut Vec<u8>, header: &PacketHeader) {
+    packet.extend_from_slice(MAGIC);
+    packet.extend_from_slice(&u32_bytes(header.sequence));
+    packet.extend_from_slice(&u32_bytes(header.sample_rate));
+    packet.push(format_code(header.format));
+    packet.push(header.channels as u8);
+}
+
+fn decode_header(packet: &[u8]) -> Option<PacketHeader> {
+    if packet.len() < HEADER_LEN || &packet[0..4] != MAGIC {
+        return None;
+    }
+
+    let header = PacketHeader {
+        sequence: read_u32(&packet[4..8]),
+        sample_rate: read_u32(&packet[8..12]),
+        format: format_from_code(packet[12])?,
+        channels: packet[13] as usize,
+    };
+    if header.sample_rate == 0 || header.channels == 0 {
+        return None;
+    }
+
+    Some(header)
+}
+
+fn format_code(format: SampleFormat) -> u8 {
+    match format {
+        SampleFormat::U8 => 1,
+        SampleFormat::S16 => 2,
+        SampleFormat::S24 => 3,
+        SampleFormat::S32 => 4,
+        SampleFormat::F32 => 5,
+        SampleFormat::F64 => 6,
+    }
+}
+
+fn format_from_code(code: u8) -> Option<SampleFormat> {
+    match code {
+        1 => Some(SampleFormat::U8),
+        2 => Some(SampleFormat::S16),
+        3 => Some(SampleFormat::S24),
+        4 => Some(SampleFormat::S32),
+        5 => Some(SampleFormat::F32),
+        6 => Some(SampleFormat::F64),
+        _ => None,
+    }
+}
+
+fn u32_bytes(value: u32) -> [u8; 4] {
+    [value as u8, (value >> 8) as u8, (value >> 16) as u8, (value >> 24) as u8]
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    bytes[0] as u32 | (bytes[1] as u32) << 8 | (bytes[2] as u32) << 16 | (bytes[3] as u32) << 24
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    fn header(sequence: u32) -> PacketHeader {
+        PacketHeader {
+            sequence,
+            sample_rate: 24000,
+            format: SampleFormat::S16,
+            channels: 1,
+        }
+    }
+
+    // A packet of FRAMES_PER_PACKET mono s16 samples at half scale.
+    fn packet(sequence: u32) -> Vec<u8> {
+        let mut packet = Vec::new();
+        encode_header(&mut packet, &header(sequence));
+        for _ in 0..FRAMES_PER_PACKET {
+            packet.extend_from_slice(&[0x00, 0x40]);
+        }
+        packet
+    }
+
+    // A source that is already playing, as if `bind` had received packet `sequence`.
+    fn playing_source(sequence: u32) -> UdpSource {
+        UdpSource {
+            socket: UdpSocket::bind("127.0.0.1:0").unwrap(),
+            receive_buffer: vec![0; MAX_PACKET_LEN],
+
+            sample_rate: 24000,
+            format: SampleFormat::S16,
+            input_channels: 1,
+            channels: 1,
+
+            packets: BTreeMap::new(),
+            jitter_frames: FRAMES_PER_PACKET * 4,
+
+            playout_start: Some(time::Instant::now()),
+            played_frames: 0,
+            next_sequence: sequence,
+            last_packet: Vec::new(),
+            concealed_packets: 0,
+
+            lost_packets: 0,
+            last_loss_report: time::Instant::now(),
+        }
+    }
+
+    #[test]
+    fn headers_round_trip() {
+        for &format in &[SampleFormat::U8, SampleFormat::S16, SampleFormat::S24,
+                         SampleFormat::S32, SampleFormat::F32, SampleFormat::F64] {
+            let mut packet = Vec::new();
+            encode_header(&mut packet, &PacketHeader {
+                sequence: 0xdeadbeef,
+                sample_rate: 48000,
+                format,
+                channels: 2,
+            });
+            assert_eq!(packet.len(), HEADER_LEN);
+
+            let decoded = decode_header(&packet).unwrap();
+            assert_eq!(decoded.sequence, 0xdeadbeef);
+            assert_eq!(decoded.sample_rate, 48000);
+            assert_eq!(decoded.format, format);
+            assert_eq!(decoded.channels, 2);
+        }
+    }
+
+    #[test]
+    fn bad_headers_are_ignored() {
+        let good = packet(0);
+        assert!(decode_header(&good[0..HEADER_LEN - 1]).is_none());
+
+        let mut bad_magic = good.clone();
+        bad_magic[0] = b'X';
+        assert!(decode_header(&bad_magic).is_none());
+
+        let mut bad_format = good.clone();
+        bad_format[12] = 0;
+        assert!(decode_header(&bad_format).is_none());
+
+        let mut no_channels = good.clone();
+        no_channels[13] = 0;
+        assert!(decode_header(&no_channels).is_none());
+
+        let mut no_sample_rate = good.clone();
+        no_sample_rate[8..12].copy_from_slice(&[0, 0, 0, 0]);
+        assert!(decode_header(&no_sample_rate).is_none());
+    }
+
+    #[test]
+    fn packets_play_in_sequence_order() {
+        let mut source = playing_source(10);
+        source.accept(&packet(11));
+        source.accept(&packet(10));
+
+        let mut samples = Vec::new();
+        assert_eq!(source.play_next(&mut samples), FRAMES_PER_PACKET);
+        assert_eq!(source.play_next(&mut samples), FRAMES_PER_PACKET);
+        assert_eq!(source.next_sequence, 12);
+        assert_eq!(samples.len(), FRAMES_PER_PACKET * 2);
+        assert!(samples.iter().all(|&x| x == 0.5));
+        assert_eq!(source.lost_packets, 0);
+    }
+
+    #[test]
+    fn late_packets_are_dropped() {
+        let mut source = playing_source(10);
+        source.accept(&packet(10));
+        source.play_next(&mut Vec::new());
+
+        source.accept(&packet(9));
+        source.accept(&packet(10));
+        assert!(source.packets.is_empty());
+    }
+
+    #[test]
+    fn lost_packets_are_concealed_with_a_fade() {
+        let mut source = playing_source(10);
+        source.accept(&packet(10));
+        source.accept(&packet(12));
+
+        let mut samples = Vec::new();
+        source.play_next(&mut samples);
+        source.play_next(&mut samples);
+        source.play_next(&mut samples);
+        assert_eq!(source.lost_packets, 1);
+        assert_eq!(samples[FRAMES_PER_PACKET], 0.5 * CONCEALMENT_FADE);
+        assert_eq!(samples[FRAMES_PER_PACKET * 2], 0.5);
+    }
+
+    #[test]
+    fn restarted_sender_is_heard_after_a_pause() {
+        let mut source = playing_source(5000);
+        source.accept(&packet(5000));
+        let mut samples = Vec::new();
+        for _ in 0..(MAX_CONCEALED_PACKETS + 2) {
+            source.play_next(&mut samples);
+        }
+
+        // The sender restarts from 0, which is only a few packets behind.
+        source.accept(&packet(0));
+        assert!(source.packets.contains_key(&0));
+        assert_eq!(source.next_sequence, 0);
+        assert!(source.playout_start.is_none());
+    }
+
+    #[test]
+    fn restarted_sender_is_heard_without_a_pause() {
+        let mut source = playing_source(5000);
+        source.accept(&packet(5000));
+        source.play_next(&mut Vec::new());
+
+        source.accept(&packet(0));
+        assert!(source.packets.contains_key(&0));
+        assert_eq!(source.next_sequence, 0);
+    }
+
+    #[test]
+    fn other_streams_are_ignored() {
+        let mut source = playing_source(0);
+        let mut stereo = Vec::new();
+        encode_header(&mut stereo, &PacketHeader { channels: 2, ..header(0) });
+        stereo.extend_from_slice(&[0; 8]);
+        source.accept(&stereo);
+        assert!(source.packets.is_empty());
+    }
+
+    // Sends packets of `frame` repeated, encoded like `send_udp` does, to a free port on
+    // 127.0.0.1 until the returned flag is set. Packets sent before the receiver binds are
+    // lost, just as with a sender that is started first.
+    fn send_on_loopback(frame: Vec<f32>) -> (String, Arc<AtomicBool>, thread::JoinHandle<()>) {
+        let address = UdpSocket::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string();
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.connect(&address).unwrap();
+        let samples: Vec<f32> = frame.iter().cloned().cycle()
+            .take(FRAMES_PER_PACKET * frame.len())
+            .collect();
+        let sending = stop.clone();
+        let handle = thread::spawn(move || {
+            let mut packet = Vec::new();
+            let mut sequence = 0;
+            while !sending.load(Ordering::SeqCst) {
+                packet.clear();
+                encode_packet(&mut packet, sequence, 24000, frame.len(), &samples);
+                let _ = socket.send(&packet);
+                sequence += 1;
+                // Faster than real time, so the jitter buffer never runs dry.
+                thread::sleep(time::Duration::from_millis(2));
+            }
+        });
+
+        (address, stop, handle)
+    }
+
+    // Read from `source` until it has played at least `count` samples.
+    fn read_samples(source: &mut UdpSource, count: usize) -> Vec<f32> {
+        let deadline = time::Instant::now() + time::Duration::from_secs(5);
+        let mut samples = Vec::new();
+        while samples.len() < count {
+            assert!(time::Instant::now() < deadline, "only received {} samples", samples.len());
+            assert!(source.read(&mut samples));
+            thread::sleep(time::Duration::from_millis(1));
+        }
+        samples
+    }
+
+    #[test]
+    fn plays_packets_sent_over_loopback() {
+        let (address, stop, handle) = send_on_loopback(vec![0.5]);
+        let mut source = UdpSource::bind(&address, 0.05, false).unwrap();
+        assert_eq!(source.sample_rate(), 24000);
+        assert_eq!(source.channels(), 1);
+
+        let samples = read_samples(&mut source, FRAMES_PER_PACKET * 4);
+        stop.store(true, Ordering::SeqCst);
+        handle.join().unwrap();
+
+        let expected = 16383.0 / 32768.0;
+        assert!(samples.iter().all(|&x| (x - expected).abs() < 1e-4), "{:?}", &samples[0..8]);
+        assert_eq!(source.lost_packets, 0);
+    }
+
+    #[test]
+    fn plays_stereo_packets_sent_over_loopback() {
+        let (address, stop, handle) = send_on_loopback(vec![0.5, -0.25]);
+        let mut stereo = UdpSource::bind(&address, 0.05, true).unwrap();
+        assert_eq!(stereo.channels(), 2);
+
+        let samples = read_samples(&mut stereo, FRAMES_PER_PACKET * 4);
+        stop.store(true, Ordering::SeqCst);
+        handle.join().unwrap();
+
+        for frame in samples.chunks(2) {
+            assert!((frame[0] - 0.5).abs() < 1e-3 && (frame[1] + 0.25).abs() < 1e-3, "{:?}", frame);
+        }
+    }
+}
@@ -0,0 +1,382 @@
+use listen::sample_format::SampleFormat;
+use listen::source::AudioSource;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::time;
+
+// == WAV Notes ==
+
+// Layout
+// Reference: http://soundfile.sapp.org/doc/WaveFormat/
+// A WAV file is a RIFF container: "RIFF" <size> "WAVE", followed by chunks of
+// <4 byte id> <u32 little endian size> <data>. Chunks are padded to an even size.
+// We only care about the "fmt " chunk (sample format) and the "data" chunk (samples).
+
+// Format tags
+// 1 is integer PCM, 3 is IEEE float, and 0xFFFE (WAVE_FORMAT_EXTENSIBLE) stores the
+// real tag in the first two bytes of the sub-format GUID.
+
+const FORMAT_PCM: u16 = 1;
+const FORMAT_IEEE_FLOAT: u16 = 3;
+const FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+pub struct WavFile {
+    pub sample_rate: u32,
+    pub channels: usize,
+    // Interleaved samples in the range [-1, 1].
+    pub samples: Vec<f32>,
+}
+
+impl WavFile {
+    pub fn open(path: &str) -> io::Result<WavFile> {
+        let mut bytes = Vec::new();
+        File::open(path)?.read_to_end(&mut bytes)?;
+
+        WavFile::parse(&bytes)
+    }
+
+    pub fn parse(bytes: &[u8]) -> io::Result<WavFile> {
+        if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+            return Err(invalid_data("not a RIFF/WAVE file"));
+        }
+
+        let mut format: Option<(SampleFormat, usize, u32)> = None;
+        let mut data: Option<&[u8]> = None;
+
+        let mut offset = 12;
+        while offset + 8 <= bytes.len() {
+            let chunk_id = &bytes[offset..offset + 4];
+            let chunk_size = read_u32(&bytes[offset + 4..offset + 8]) as usize;
+            let chunk_start = offset + 8;
+            // Streamed files may leave the size unset, so never read past the end.
+            let chunk_end = usize::min(bytes.len(), chunk_start.saturating_add(chunk_size));
+            let chunk = &bytes[chunk_start..chunk_end];
+
+            if chunk_id == b"fmt " {
+                format = Some(parse_format_chunk(chunk)?);
+            } else if chunk_id == b"data" {
+                data = Some(chunk);
+            }
+
+            offset = chunk_end + (chunk_end - chunk_start) % 2;
+        }
+
+        let (format, channels, sample_rate) = match format {
+            Some(x) => x,
+            None => return Err(invalid_data("missing fmt chunk")),
+        };
+        let data = match data {
+            Some(x) => x,
+            None => return Err(invalid_data("missing data chunk")),
+        };
+
+        let bytes_per_sample = format.bytes_per_sample();
+        let sample_count = data.len() / bytes_per_sample / channels * channels;
+        let mut samples = Vec::with_capacity(sample_count);
+        for i in 0..sample_count {
+            let start = i * bytes_per_sample;
+            samples.push(format.decode(&data[start..start + bytes_per_sample]));
+        }
+
+        Ok(WavFile {
+            sample_rate,
+            channels,
+            samples,
+        })
+    }
+
+    // Average all channels together into a single channel.
+    pub fn to_mono(&self) -> Vec<f32> {
+        self.samples
+            .chunks(self.channels)
+            .map(|frame| frame.iter().sum::<f32>() / self.channels as f32)
+            .collect()
+    }
+
+    // Keep only the first two channels, as interleaved left and right samples.
+    pub fn to_stereo(&self) -> Vec<f32> {
+        let mut output = Vec::with_capacity(self.samples.len() / self.channels * 2);
+        for frame in self.samples.chunks(self.channels) {
+            output.push(frame[0]);
+            output.push(frame[usize::min(1, self.channels - 1)]);
+        }
+
+        output
+    }
+}
+
+// Plays a WAV file back in real time, as if it were being recorded.
+pub struct WavSource {
+    samples: Vec<f32>,
+    sample_rate: u32,
+    channels: usize,
+    loop_playback: bool,
+
+    start: Option<time::Instant>,
+    played_frames: u64,
+    position: usize,
+}
+
+impl WavSource {
+    // Plays stereo files as stereo when `stereo` is set, and everything else as mono.
+    pub fn new(wav: WavFile, loop_playback: bool, stereo: bool) -> WavSource {
+        let channels = if stereo && wav.channels >= 2 { 2 } else { 1 };
+
+        WavSource {
+            samples: if channels == 2 { wav.to_stereo() } else { wav.to_mono() },
+            sample_rate: wav.sample_rate,
+            channels,
+            loop_playback,
+
+            start: None,
+            played_frames: 0,
+            position: 0,
+        }
+    }
+}
+
+impl AudioSource for WavSource {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn channels(&self) -> usize {
+        self.channels
+    }
+
+    fn read(&mut self, samples: &mut Vec<f32>) -> bool {
+        if self.samples.is_empty() {
+            return false;
+        }
+
+        let start = *self.start.get_or_insert_with(time::Instant::now);
+        let elapsed = start.elapsed();
+        let elapsed_nanos = elapsed.as_secs() * 1_000_000_000 + elapsed.subsec_nanos() as u64;
+        let due_frames = elapsed_nanos * self.sample_rate as u64 / 1_000_000_000;
+
+        while self.played_frames < due_frames {
+            if self.position >= self.samples.len() {
+                if !self.loop_playback {
+                    return false;
+                }
+                self.position = 0;
+            }
+
+            let count = usize::min(
+                (due_frames - self.played_frames) as usize,
+                (self.samples.len() - self.position) / self.channels);
+            let end = self.position + count * self.channels;
+            samples.extend_from_slice(&self.samples[self.position..end]);
+            self.position = end;
+            self.played_frames += count as u64;
+        }
+
+        true
+    }
+}
+
+fn parse_format_chunk(chunk: &[u8]) -> io::Result<(SampleFormat, usize, u32)> {
+    if chunk.len() < 16 {
+        return Err(invalid_data("fmt chunk is too short"));
+    }
+
+    let mut format_tag = read_u16(&chunk[0..2]);
+    let channels = read_u16(&chunk[2..4]) as usize;
+    let sample_rate = read_u32(&chunk[4..8]);
+    let bits_per_sample = read_u16(&chunk[14..16]);
+
+    if format_tag == FORMAT_EXTENSIBLE {
+        if chunk.len() < 26 {
+            return Err(invalid_data("extensible fmt chunk is too short"));
+        }
+        format_tag = read_u16(&chunk[24..26]);
+    }
+
+    let format = match (format_tag, bits_per_sample) {
+        (FORMAT_PCM, 8) => SampleFormat::U8,
+        (FORMAT_PCM, 16) => SampleFormat::S16,
+        (FORMAT_PCM, 24) => SampleFormat::S24,
+        (FORMAT_PCM, 32) => SampleFormat::S32,
+        (FORMAT_IEEE_FLOAT, 32) => SampleFormat::F32,
+        (FORMAT_IEEE_FLOAT, 64) => SampleFormat::F64,
+        _ => return Err(invalid_data(&format!(
+            "unsupported sample format (tag {}, {} bits)", format_tag, bits_per_sample))),
+    };
+
+    if channels == 0 || sample_rate == 0 {
+        return Err(invalid_data("fmt chunk has no channels or no sample rate"));
+    }
+
+    Ok((format, channels, sample_rate))
+}
+
+fn read_u16(bytes: &[u8]) -> u16 {
+    bytes[0] as u16 | (bytes[1] as u16) << 8
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    bytes[0] as u32 | (bytes[1] as u32) << 8 | (bytes[2] as u32) << 16 | (bytes[3] as u32) << 24
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn push_u16(bytes: &mut Vec<u8>, value: u16) {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn push_u32(bytes: &mut Vec<u8>, value: u32) {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn format_chunk(format_tag: u16, channels: u16, sample_rate: u32,
+                    bits_per_sample: u16) -> Vec<u8> {
+        let block_align = channels * bits_per_sample / 8;
+        let mut chunk = Vec::new();
+        push_u16(&mut chunk, format_tag);
+        push_u16(&mut chunk, channels);
+        push_u32(&mut chunk, sample_rate);
+        push_u32(&mut chunk, sample_rate * block_align as u32);
+        push_u16(&mut chunk, block_align);
+        push_u16(&mut chunk, bits_per_sample);
+        chunk
+    }
+
+    // The 40 byte WAVE_FORMAT_EXTENSIBLE layout, with the real tag at the start of the GUID.
+    fn extensible_format_chunk(sub_format: u16, channels: u16, sample_rate: u32,
+                               bits_per_sample: u16) -> Vec<u8> {
+        let mut chunk = format_chunk(FORMAT_EXTENSIBLE, channels, sample_rate, bits_per_sample);
+        push_u16(&mut chunk, 22);
+        push_u16(&mut chunk, bits_per_sample);
+        push_u32(&mut chunk, 0x3);
+        push_u16(&mut chunk, sub_format);
+        chunk.extend_from_slice(&[0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00,
+                                  0x00, 0xaa, 0x00, 0x38, 0x9b, 0x71]);
+        chunk
+    }
+
+    fn riff(chunks: &[(&[u8], &[u8])]) -> Vec<u8> {
+        let mut body = b"WAVE".to_vec();
+        for &(id, data) in chunks {
+            body.extend_from_slice(id);
+            push_u32(&mut body, data.len() as u32);
+            body.extend_from_slice(data);
+            if data.len() % 2 == 1 {
+                body.push(0);
+            }
+        }
+
+        let mut bytes = b"RIFF".to_vec();
+        push_u32(&mut bytes, body.len() as u32);
+        bytes.extend_from_slice(&body);
+        bytes
+    }
+
+    fn wav_bytes(format: &[u8], data: &[u8]) -> Vec<u8> {
+        riff(&[(b"fmt ", format), (b"data", data)])
+    }
+
+    #[test]
+    fn parses_8_bit_mono() {
+        let format = format_chunk(FORMAT_PCM, 1, 8000, 8);
+        let wav = WavFile::parse(&wav_bytes(&format, &[0, 128, 192])).unwrap();
+        assert_eq!(wav.sample_rate, 8000);
+        assert_eq!(wav.channels, 1);
+        assert_eq!(wav.samples, vec![-1.0, 0.0, 0.5]);
+    }
+
+    #[test]
+    fn parses_16_bit_stereo() {
+        let data = [0x00, 0x40, 0x00, 0xc0, 0x00, 0x80, 0x00, 0x00];
+        let format = format_chunk(FORMAT_PCM, 2, 44100, 16);
+        let wav = WavFile::parse(&wav_bytes(&format, &data)).unwrap();
+        assert_eq!(wav.sample_rate, 44100);
+        assert_eq!(wav.channels, 2);
+        assert_eq!(wav.samples, vec![0.5, -0.5, -1.0, 0.0]);
+        assert_eq!(wav.to_mono(), vec![0.0, -0.5]);
+        assert_eq!(wav.to_stereo(), vec![0.5, -0.5, -1.0, 0.0]);
+    }
+
+    #[test]
+    fn parses_24_bit() {
+        let data = [0x00, 0x00, 0x40, 0x00, 0x00, 0xc0];
+        let format = format_chunk(FORMAT_PCM, 1, 48000, 24);
+        let wav = WavFile::parse(&wav_bytes(&format, &data)).unwrap();
+        assert_eq!(wav.samples, vec![0.5, -0.5]);
+    }
+
+    #[test]
+    fn parses_float() {
+        let mut data = Vec::new();
+        data.extend_from_slice(&0.25f32.to_bits().to_le_bytes());
+        data.extend_from_slice(&(-0.75f32).to_bits().to_le_bytes());
+        let format = format_chunk(FORMAT_IEEE_FLOAT, 1, 48000, 32);
+        let wav = WavFile::parse(&wav_bytes(&format, &data)).unwrap();
+        assert_eq!(wav.samples, vec![0.25, -0.75]);
+
+        let data = 0.125f64.to_bits().to_le_bytes();
+        let format = format_chunk(FORMAT_IEEE_FLOAT, 1, 48000, 64);
+        let wav = WavFile::parse(&wav_bytes(&format, &data)).unwrap();
+        assert_eq!(wav.samples, vec![0.125]);
+    }
+
+    #[test]
+    fn parses_extensible() {
+        let data = [0x00, 0x40, 0x00, 0xc0];
+        let format = extensible_format_chunk(FORMAT_PCM, 2, 96000, 16);
+        let wav = WavFile::parse(&wav_bytes(&format, &data)).unwrap();
+        assert_eq!(wav.sample_rate, 96000);
+        assert_eq!(wav.channels, 2);
+        assert_eq!(wav.samples, vec![0.5, -0.5]);
+
+        let data = 0.5f32.to_bits().to_le_bytes();
+        let format = extensible_format_chunk(FORMAT_IEEE_FLOAT, 1, 48000, 32);
+        assert_eq!(WavFile::parse(&wav_bytes(&format, &data)).unwrap().samples, vec![0.5]);
+    }
+
+    #[test]
+    fn skips_other_chunks_and_their_padding() {
+        let format = format_chunk(FORMAT_PCM, 1, 8000, 16);
+        let bytes = riff(&[(b"LIST", b"odd"), (b"fmt ", &format), (b"data", &[0x00, 0x40])]);
+        assert_eq!(WavFile::parse(&bytes).unwrap().samples, vec![0.5]);
+    }
+
+    #[test]
+    fn drops_a_partial_frame_at_the_end() {
+        let data = [0x00, 0x40, 0x00, 0xc0, 0x00];
+        let format = format_chunk(FORMAT_PCM, 2, 8000, 16);
+        let wav = WavFile::parse(&wav_bytes(&format, &data)).unwrap();
+        assert_eq!(wav.samples, vec![0.5, -0.5]);
+    }
+
+    #[test]
+    fn reads_a_streamed_data_chunk_to_the_end() {
+        let format = format_chunk(FORMAT_PCM, 1, 8000, 16);
+        let mut bytes = wav_bytes(&format, &[0x00, 0x40, 0x00, 0xc0]);
+        let size_offset = bytes.len() - 8;
+        bytes[size_offset..size_offset + 4].copy_from_slice(&[0xff; 4]);
+        assert_eq!(WavFile::parse(&bytes).unwrap().samples, vec![0.5, -0.5]);
+    }
+
+    #[test]
+    fn rejects_malformed_files() {
+        let format = format_chunk(FORMAT_PCM, 1, 8000, 16);
+        let rejected = |bytes: &[u8]| WavFile::parse(bytes).err().unwrap().kind();
+
+        assert_eq!(rejected(b"RIFX\0\0\0\0WAVE"), io::ErrorKind::InvalidData);
+        assert_eq!(rejected(&riff(&[(b"fmt ", &format)])), io::ErrorKind::InvalidData);
+        assert_eq!(rejected(&riff(&[(b"data", &[0, 0])])), io::ErrorKind::InvalidData);
+        assert_eq!(rejected(&wav_bytes(&format[0..14], &[0, 0])), io::ErrorKind::InvalidData);
+        // ADPCM.
+        assert_eq!(rejected(&wav_bytes(&format_chunk(2, 1, 8000, 4), &[0, 0])),
+                   io::ErrorKind::InvalidData);
+        assert_eq!(rejected(&wav_bytes(&format_chunk(FORMAT_PCM, 0, 8000, 16), &[0, 0])),
+                   io::ErrorKind::InvalidData);
+    }
+}
@@ -9,8 +9,11 @@ extern crate rand;
 extern crate rustfft;
 extern crate sphinxad_sys;
 
-use argparse::{ArgumentParser, Store, StoreTrue};
-use std::sync::mpsc;
+use argparse::{ArgumentParser, List, Store, StoreTrue};
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::process;
 use std::thread;
 
 #[macro_use]
@@ -26,10 +29,53 @@ fn main() {
     let mut selected_visualizer = "".to_string();
     let mut selected_screen = "".to_string();
     let mut size = 128;
+    let mut fps: f32 = 60.0;
     let mut samples_per_second = 24000;
     let mut window_sample_size = 1024;
+    let mut hop_size = 0;
+    let mut window_function = "rectangular".to_string();
     let mut amplitude_scalar = 16.0;
+    let mut fixed_gain = false;
+    let mut agc_attack_seconds = 0.1;
+    let mut agc_release_seconds = 3.0;
+    let mut agc_min_gain = 1.0;
+    let mut agc_max_gain = 1000.0;
+    let mut noise_gate_db = -60.0;
+    let mut agc_per_band = false;
+    let mut envelope_attack_seconds = 0.01;
+    let mut envelope_decay_seconds = 0.25;
+    let mut peak_hold_seconds = 0.5;
+    let mut peak_gravity = 4.0;
+    let mut silence_threshold_db = -50.0;
+    let mut idle_timeout_seconds = 10.0;
+    let mut band_scale = "log".to_string();
+    let mut band_count = 16;
+    let mut min_hz = 40.0;
+    let mut max_hz = 16000.0;
+    let mut low_threshold_hz = 1000.0;
+    let mut mid_threshold_hz = 4000.0;
+    let mut high_threshold_hz = 20000.0;
     let mut use_fake_audio = false;
+    let mut fake_signal = "demo".to_string();
+    let mut fake_bpm: f32 = 120.0;
+    let mut fake_pattern = "".to_string();
+    let mut seed = 0;
+    let mut wav_path = "".to_string();
+    let mut loop_playback = false;
+    let mut pcm_path = "".to_string();
+    let mut pcm_format = "s16".to_string();
+    let mut pcm_channels = 1;
+    let mut udp_address = "".to_string();
+    let mut udp_jitter_ms: f32 = 60.0;
+    let mut udp_send_address = "".to_string();
+    let mut record_frames_path = "".to_string();
+    let mut replay_frames_path = "".to_string();
+    let mut stereo = false;
+    let mut device = "".to_string();
+    let mut list_devices = false;
+    let mut command: Vec<String> = Vec::new();
+    let mut output_path = "-".to_string();
+    let mut output_format = "csv".to_string();
     {
         let mut parser = ArgumentParser::new();
         parser.set_description("LED Music Visualizer");
@@ -42,34 +88,477 @@ fn main() {
         parser.refer(&mut size)
               .add_option(&["--size"], Store,
                           "Window size.");
+        parser.refer(&mut fps)
+              .add_option(&["--fps"], Store,
+                          "Frames drawn per second, independent of how often audio is analyzed.");
         parser.refer(&mut samples_per_second)
               .add_option(&["--samples_per_second"], Store,
                           "Number of samples per second to record from the micrphone.");
         parser.refer(&mut window_sample_size)
               .add_option(&["--window_sample_size"], Store,
                           "Number of samples to process at a time.");
+        parser.refer(&mut hop_size)
+              .add_option(&["--hop_size"], Store,
+                          "Number of samples between the starts of consecutive windows. Defaults to a whole window, with no overlap.");
+        parser.refer(&mut window_function)
+              .add_option(&["--window_function"], Store,
+                          "Window function: rectangular, hann, hamming or blackman_harris. The tapered ones need overlap, e.g. a hop of half a window.");
         parser.refer(&mut amplitude_scalar)
               .add_option(&["--amplitude_scalar"], Store,
-                          "Multiplier for audio ampltitude.");
+                          "Multiplier for audio ampltitude. The starting gain of the automatic gain control.");
+        parser.refer(&mut fixed_gain)
+              .add_option(&["--fixed_gain"], StoreTrue,
+                          "Always use the amplitude scalar, with no automatic gain control or noise gate.");
+        parser.refer(&mut agc_attack_seconds)
+              .add_option(&["--agc_attack"], Store,
+                          "Seconds for the gain to back off when the audio gets louder.");
+        parser.refer(&mut agc_release_seconds)
+              .add_option(&["--agc_release"], Store,
+                          "Seconds for the gain to recover when the audio gets quieter.");
+        parser.refer(&mut agc_min_gain)
+              .add_option(&["--agc_min_gain"], Store,
+                          "Lowest gain the automatic gain control will use.");
+        parser.refer(&mut agc_max_gain)
+              .add_option(&["--agc_max_gain"], Store,
+                          "Highest gain the automatic gain control will use.");
+        parser.refer(&mut noise_gate_db)
+              .add_option(&["--noise_gate_db"], Store,
+                          "Audio quieter than this (dB relative to full scale) is treated as silence.");
+        parser.refer(&mut agc_per_band)
+              .add_option(&["--agc_per_band"], StoreTrue,
+                          "Normalize each band separately.");
+        parser.refer(&mut envelope_attack_seconds)
+              .add_option(&["--envelope_attack"], Store,
+                          "Seconds for the smoothed powers, buckets and bands to rise.");
+        parser.refer(&mut envelope_decay_seconds)
+              .add_option(&["--envelope_decay"], Store,
+                          "Seconds for the smoothed powers, buckets and bands to fall.");
+        parser.refer(&mut peak_hold_seconds)
+              .add_option(&["--peak_hold"], Store,
+                          "Seconds a peak holds before it starts to fall.");
+        parser.refer(&mut peak_gravity)
+              .add_option(&["--peak_gravity"], Store,
+                          "How quickly falling peaks speed up, in units per second squared.");
+        parser.refer(&mut silence_threshold_db)
+              .add_option(&["--silence_db"], Store,
+                          "Audio quieter than this (dB relative to full scale) counts as silence.");
+        parser.refer(&mut idle_timeout_seconds)
+              .add_option(&["--idle_timeout"], Store,
+                          "Seconds of silence before switching to the ambient animation. 0 to never switch.");
+        parser.refer(&mut band_scale)
+              .add_option(&["--bands"], Store,
+                          "Band layout: linear, log, octave, third_octave or mel.");
+        parser.refer(&mut band_count)
+              .add_option(&["--band_count"], Store,
+                          "Number of linear, log or mel bands.");
+        parser.refer(&mut min_hz)
+              .add_option(&["--min_hz"], Store,
+                          "Lower edge of the lowest band.");
+        parser.refer(&mut max_hz)
+              .add_option(&["--max_hz"], Store,
+                          "Upper edge of the highest band.");
+        parser.refer(&mut low_threshold_hz)
+              .add_option(&["--low_threshold_hz"], Store,
+                          "Frequencies up to this count towards the low power.");
+        parser.refer(&mut mid_threshold_hz)
+              .add_option(&["--mid_threshold_hz"], Store,
+                          "Frequencies up to this count towards the mid power.");
+        parser.refer(&mut high_threshold_hz)
+              .add_option(&["--high_threshold_hz"], Store,
+                          "Frequencies up to this count towards the high power.");
         parser.refer(&mut use_fake_audio)
               .add_option(&["--fake"], StoreTrue,
-                          "Use fake audio.");
+                          "Use a synthesized test signal instead of the microphone.");
+        parser.refer(&mut fake_signal)
+              .add_option(&["--fake_signal"], Store,
+                          "Test signal: sweep, pink_noise, drums, chords or demo (drums and chords).");
+        parser.refer(&mut fake_bpm)
+              .add_option(&["--fake_bpm"], Store,
+                          "Tempo of the drums and chords.");
+        parser.refer(&mut fake_pattern)
+              .add_option(&["--fake_pattern"], Store,
+                          "Drum pattern as kick,snare,hat steps of x and ., e.g. x...x...x...x...,....x.......x...,x.x.x.x.x.x.x.x.");
+        parser.refer(&mut seed)
+              .add_option(&["--seed"], Store,
+                          "Seed for the test signal's noise. The same seed always plays the same audio.");
+        parser.refer(&mut wav_path)
+              .add_option(&["--wav"], Store,
+                          "Play a WAV file instead of recording the microphone.");
+        parser.refer(&mut loop_playback)
+              .add_option(&["--loop"], StoreTrue,
+                          "Loop the WAV file or frame recording instead of exiting at the end.");
+        parser.refer(&mut pcm_path)
+              .add_option(&["--pcm"], Store,
+                          "Read raw PCM from a file or named pipe instead of recording the microphone. - for stdin.");
+        parser.refer(&mut pcm_format)
+              .add_option(&["--pcm_format"], Store,
+                          "Sample format of the raw PCM: u8, s16, s24, s32, f32 or f64, little endian.");
+        parser.refer(&mut pcm_channels)
+              .add_option(&["--pcm_channels"], Store,
+                          "Number of interleaved channels in the raw PCM. Its rate is --samples_per_second.");
+        parser.refer(&mut udp_address)
+              .add_option(&["--udp"], Store,
+                          "Play audio sent over the network with --udp_send, listening on this port or address.");
+        parser.refer(&mut udp_jitter_ms)
+              .add_option(&["--udp_jitter_ms"], Store,
+                          "Milliseconds of network audio to buffer against late packets.");
+        parser.refer(&mut udp_send_address)
+              .add_option(&["--udp_send"], Store,
+                          "Send the audio to another visualizer's --udp port (host:port) instead of showing it.");
+        parser.refer(&mut record_frames_path)
+              .add_option(&["--record_frames"], Store,
+                          "Record every analyzed frame to a file, to replay later.");
+        parser.refer(&mut replay_frames_path)
+              .add_option(&["--replay_frames"], Store,
+                          "Replay frames recorded with --record_frames instead of listening.");
+        parser.refer(&mut device)
+              .add_option(&["--device"], Store,
+                          "ALSA device to record from, such as plughw:2,0. Defaults to the default device.");
+        parser.refer(&mut list_devices)
+              .add_option(&["--list_devices"], StoreTrue,
+                          "List the ALSA capture devices and exit.");
+        parser.refer(&mut stereo)
+              .add_option(&["--stereo"], StoreTrue,
+                          "Analyze the left and right channels of stereo sources separately. The microphone is mono; pipe it in with --pcm for stereo.");
+        parser.refer(&mut output_path)
+              .add_option(&["--output"], Store,
+                          "File for the analyze command to write to. - for stdout.");
+        parser.refer(&mut output_format)
+              .add_option(&["--output_format"], Store,
+                          "Format for the analyze command: csv or json.");
+        parser.refer(&mut command)
+              .add_argument("command", List,
+                          "Run a command instead of the visualizer. `analyze song.wav` writes every analyzed frame of a WAV file as fast as possible.");
         parser.parse_args_or_exit();
     }
 
-    let (audio_tx, audio_rx) = mpsc::sync_channel::<audio::AudioFrame>(1);
+    if list_devices {
+        match listen::list_capture_devices() {
+            Ok(devices) => {
+                if devices.is_empty() {
+                    println!("No capture devices found.");
+                }
+                for (name, description) in devices {
+                    println!("{}\t{}", name, description);
+                }
+                process::exit(0);
+            }
+            Err(e) => {
+                println!("Could not list capture devices: {}", e);
+                process::exit(1);
+            }
+        }
+    }
+
+    if fps.is_nan() || fps <= 0.0 {
+        println!("Frames per second must be above 0.");
+        process::exit(1);
+    }
+    if udp_jitter_ms.is_nan() || udp_jitter_ms <= 0.0 {
+        println!("UDP jitter buffer must be above 0 milliseconds.");
+        process::exit(1);
+    }
+
+    // Overlapping windows analyze more often, which the per-frame animations weren't tuned
+    // for, so it's opt in, and so is a tapered window that needs it.
+    if hop_size == 0 {
+        hop_size = window_sample_size;
+    }
+    if hop_size == 0 || hop_size > window_sample_size {
+        println!("Hop size must be between 1 and the window sample size.");
+        process::exit(1);
+    }
+
+    let window_function = match audio::WindowFunction::from_name(&window_function) {
+        Some(x) => x,
+        None => {
+            println!("Unknown window function: {}", window_function);
+            process::exit(1);
+        }
+    };
+
+    let pcm_format = match listen::SampleFormat::from_name(&pcm_format) {
+        Some(x) => listen::PcmFormat {
+            sample_format: x,
+            sample_rate: samples_per_second,
+            channels: pcm_channels,
+        },
+        None => {
+            println!("Unknown PCM sample format: {}", pcm_format);
+            process::exit(1);
+        }
+    };
+
+    let synth_config = if use_fake_audio {
+        let signal = match listen::Signal::from_name(&fake_signal) {
+            Some(x) => x,
+            None => {
+                println!("Unknown test signal: {}", fake_signal);
+                process::exit(1);
+            }
+        };
+        let pattern = if fake_pattern.is_empty() {
+            listen::DrumPattern::four_on_the_floor()
+        } else {
+            match listen::DrumPattern::parse(&fake_pattern) {
+                Some(x) => x,
+                None => {
+                    println!("Drum patterns need kick,snare,hat steps of equal length: {}", fake_pattern);
+                    process::exit(1);
+                }
+            }
+        };
+        if fake_bpm.is_nan() || fake_bpm <= 0.0 {
+            println!("The test signal's BPM must be above 0.");
+            process::exit(1);
+        }
+
+        Some(listen::SynthConfig {
+            signal,
+            sample_rate: samples_per_second,
+            bpm: fake_bpm,
+            pattern,
+            seed,
+        })
+    } else {
+        None
+    };
 
-	if !use_fake_audio {
-		thread::spawn(move || {
-			listen::visualize_microphone(audio_tx, samples_per_second, window_sample_size, amplitude_scalar);
-		});
-	} else {
-		thread::spawn(move || {
-			listen::visualize_fake(audio_tx);
-		});
-	}
+    let band_scale = match audio::BandScale::from_name(&band_scale) {
+        Some(x) => x,
+        None => {
+            println!("Unknown band layout: {}", band_scale);
+            process::exit(1);
+        }
+    };
+
+    let listener_config = listen::ListenerConfig {
+        window_sample_size,
+        hop_size,
+        window_function,
+        gain_config: audio::GainConfig {
+            automatic: !fixed_gain,
+            initial_gain: amplitude_scalar,
+            attack_seconds: agc_attack_seconds,
+            release_seconds: agc_release_seconds,
+            min_gain: agc_min_gain,
+            max_gain: agc_max_gain,
+            noise_gate_db,
+            per_band: agc_per_band,
+        },
+        band_config: audio::BandConfig {
+            scale: band_scale,
+            band_count,
+            min_hz,
+            max_hz,
+            low_threshold_hz,
+            mid_threshold_hz,
+            high_threshold_hz,
+        },
+        envelope_config: audio::EnvelopeConfig {
+            attack_seconds: envelope_attack_seconds,
+            decay_seconds: envelope_decay_seconds,
+            peak_hold_seconds,
+            peak_gravity,
+        },
+        silence_threshold_db,
+    };
+
+    if !command.is_empty() {
+        if command[0] != "analyze" || command.len() != 2 {
+            println!("Unknown command: {}. The only command is `analyze song.wav`.", command.join(" "));
+            process::exit(1);
+        }
+        analyze(&command[1], stereo, listener_config, &output_path, &output_format);
+        return;
+    }
+
+    let source_options = AudioSourceOptions {
+        synth_config,
+        wav_path,
+        loop_playback,
+        pcm_path,
+        pcm_format,
+        udp_address,
+        udp_jitter_seconds: udp_jitter_ms / 1000.0,
+        device,
+        samples_per_second,
+        stereo,
+    };
+
+    // Stream the audio to another visualizer instead of showing it.
+    if !udp_send_address.is_empty() {
+        let source = create_audio_source(&source_options);
+        if let Err(e) = listen::send_udp(source, &udp_send_address) {
+            println!("Could not send audio to {}: {}", udp_send_address, e);
+            process::exit(1);
+        }
+        return;
+    }
+
+    let (audio_tx, audio_rx) = listen::mailbox(audio::AudioFrame::merge_skipped);
+
+    // Record whatever the visualizer is about to see, from any source.
+    let (frame_tx, recorder): (Box<dyn listen::Sink<audio::AudioFrame> + Send>, _) =
+        if !record_frames_path.is_empty() {
+            let writer = match listen::FrameWriter::create(&record_frames_path) {
+                Ok(x) => x,
+                Err(e) => {
+                    println!("Could not create frame recording {}: {}", record_frames_path, e);
+                    process::exit(1);
+                }
+            };
+            let (recorder, handle) = listen::record_frames(audio_tx, writer);
+            (Box::new(recorder), Some(handle))
+        } else {
+            (Box::new(audio_tx), None)
+        };
+    // Let the recording catch up before exiting at the end of the source.
+    let finish_recording = move || {
+        if let Some(handle) = recorder {
+            let _ = handle.join();
+        }
+    };
+
+    if !replay_frames_path.is_empty() {
+        let reader = match listen::FrameReader::open(&replay_frames_path) {
+            Ok(x) => x,
+            Err(e) => {
+                println!("Could not open frame recording {}: {}", replay_frames_path, e);
+                process::exit(1);
+            }
+        };
+        thread::spawn(move || {
+            if let Err(e) = listen::replay_frames(reader, frame_tx, loop_playback) {
+                println!("Could not read frame recording: {}", e);
+                process::exit(1);
+            }
+            finish_recording();
+            println!("Frame recording ended.");
+            process::exit(0);
+        });
+    } else {
+        let source = create_audio_source(&source_options);
+        thread::spawn(move || {
+            listen::listen(source, frame_tx, listener_config);
+            finish_recording();
+            println!("Audio source ended.");
+            process::exit(0);
+        });
+    }
 
-    let visualizer = visualizer::Visualizer::new(selected_visualizer);
+    let visualizer = visualizer::Visualizer::new(selected_visualizer, idle_timeout_seconds);
     let screen = screen::create_screen(selected_screen);
-    gfx::run(visualizer, screen, audio_rx, size);
+    gfx::run(visualizer, screen, audio_rx, size, fps);
+}
+
+// Write the analysis of every window of a WAV file to a file or stdout.
+fn analyze(wav_path: &str, stereo: bool, config: listen::ListenerConfig, output_path: &str,
+    output_format: &str) {
+    // Errors go to stderr, so they never end up in the frames written to stdout.
+    let format = match listen::OutputFormat::from_name(output_format) {
+        Some(x) => x,
+        None => {
+            eprintln!("Unknown output format: {}", output_format);
+            process::exit(1);
+        }
+    };
+
+    let wav = match listen::WavFile::open(wav_path) {
+        Ok(x) => x,
+        Err(e) => {
+            eprintln!("Could not open WAV file {}: {}", wav_path, e);
+            process::exit(1);
+        }
+    };
+
+    let stdout = io::stdout();
+    let mut output: Box<dyn io::Write> = if output_path == "-" {
+        Box::new(BufWriter::new(stdout.lock()))
+    } else {
+        match File::create(output_path) {
+            Ok(x) => Box::new(BufWriter::new(x)),
+            Err(e) => {
+                eprintln!("Could not create {}: {}", output_path, e);
+                process::exit(1);
+            }
+        }
+    };
+
+    match listen::analyze_wav(&wav, stereo, config, format, &mut output) {
+        Ok(frame_count) => {
+            // Keep stdout clean for the frames.
+            if output_path != "-" {
+                println!("Wrote {} frames to {}.", frame_count, output_path);
+            }
+        }
+        // Whatever was reading the output, like `head`, stopped early.
+        Err(ref e) if e.kind() == io::ErrorKind::BrokenPipe => {}
+        Err(e) => {
+            eprintln!("Could not write the analysis: {}", e);
+            process::exit(1);
+        }
+    }
+}
+
+// Where to get audio from. The test signal, WAV file, PCM input and UDP port are tried in
+// that order, and the microphone is used if none of them are set.
+struct AudioSourceOptions {
+    synth_config: Option<listen::SynthConfig>,
+    wav_path: String,
+    loop_playback: bool,
+    pcm_path: String,
+    pcm_format: listen::PcmFormat,
+    udp_address: String,
+    udp_jitter_seconds: f32,
+    device: String,
+    samples_per_second: u32,
+    stereo: bool,
+}
+
+fn create_audio_source(options: &AudioSourceOptions) -> Box<dyn listen::AudioSource> {
+    if let Some(ref synth_config) = options.synth_config {
+        return Box::new(listen::SynthSource::new(synth_config.clone(), options.stereo));
+    }
+
+    if !options.wav_path.is_empty() {
+        let wav = match listen::WavFile::open(&options.wav_path) {
+            Ok(x) => x,
+            Err(e) => {
+                println!("Could not open WAV file {}: {}", options.wav_path, e);
+                process::exit(1);
+            }
+        };
+
+        return Box::new(listen::WavSource::new(wav, options.loop_playback, options.stereo));
+    }
+
+    if !options.pcm_path.is_empty() {
+        return match listen::PcmSource::open(&options.pcm_path, options.pcm_format, options.stereo) {
+            Ok(x) => Box::new(x),
+            Err(e) => {
+                println!("Could not open PCM input {}: {}", options.pcm_path, e);
+                process::exit(1);
+            }
+        };
+    }
+
+    if !options.udp_address.is_empty() {
+        return match listen::UdpSource::bind(&options.udp_address, options.udp_jitter_seconds,
+                                             options.stereo) {
+            Ok(x) => Box::new(x),
+            Err(e) => {
+                println!("Could not receive audio on udp {}: {}", options.udp_address, e);
+                process::exit(1);
+            }
+        };
+    }
+
+    match listen::MicrophoneSource::open(&options.device, options.samples_per_second) {
+        Ok(x) => Box::new(x),
+        Err(e) => {
+            println!("{}", e);
+            process::exit(1);
+        }
+    }
 }
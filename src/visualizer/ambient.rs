@@ -0,0 +1,167 @@
+use audio;
+use gfx;
+use gfx::gl;
+use std::f32::consts::PI;
+use std::mem;
+use std::ptr;
+use std::time;
+use visualizer::visualizer::SubVisualizer;
+
+const NUM_SQUARES: usize = 1;
+const NUM_VERTICIES_PER_SQUARE: usize = 6;
+
+// Seconds per breath, and per trip around the color wheel.
+const BREATH_SECONDS: f32 = 6.0;
+const HUE_CYCLE_SECONDS: f32 = 90.0;
+
+// Shown while nothing is playing, so the disk doesn't look dead between songs.
+// Driven by the clock rather than the audio, since there is no audio to follow.
+pub struct AmbientVisualizer {
+    program_id: u32,
+    framebuffer_id: u32,
+    vertex_data: Vec<f32>,
+
+    start: time::Instant,
+    breath: f32,
+    hue: f32,
+}
+
+impl SubVisualizer for AmbientVisualizer {
+    fn new() -> AmbientVisualizer {
+        AmbientVisualizer {
+            program_id: 0,
+            framebuffer_id: 0,
+            vertex_data: generate_vertex_data(),
+
+            start: time::Instant::now(),
+            breath: 0.0,
+            hue: 0.0,
+        }
+    }
+
+    fn post_setup(&mut self, program_id: u32, framebuffer_id: u32) {
+        self.program_id = program_id;
+        self.framebuffer_id = framebuffer_id;
+    }
+
+    fn update(&mut self, _audio_frame: audio::AudioFrame, _seconds: f32) {
+        let elapsed = self.start.elapsed();
+        let seconds = elapsed.as_secs() as f32 + elapsed.subsec_nanos() as f32 / 1_000_000_000.0;
+
+        self.breath = 0.5 - 0.5 * (seconds / BREATH_SECONDS * PI * 2.0).cos();
+        self.hue = (seconds / HUE_CYCLE_SECONDS).fract();
+    }
+
+    fn render_to_texture(&self, gl: &gfx::gl::Gl) {
+        unsafe {
+            gl_try!(gl; gl.UseProgram(self.program_id));
+
+            let mut vb = 0;
+            gl_try!(gl; gl.GenBuffers(1, &mut vb));
+            gl_try!(gl; gl.BindBuffer(gl::ARRAY_BUFFER, vb));
+            gl_try!(gl; gl.BufferData(
+                gl::ARRAY_BUFFER,
+                (self.vertex_data.len() * mem::size_of::<f32>()) as gl::types::GLsizeiptr,
+                self.vertex_data.as_ptr() as *const _,
+                gl::STATIC_DRAW,
+            ));
+
+            let mut vao = 0;
+            gl_try!(gl; gl.GenVertexArrays(1, &mut vao));
+            gl_try!(gl; gl.BindVertexArray(vao));
+
+            let pos_attrib = gl_try!(gl; gl.GetAttribLocation(self.program_id, b"position\0".as_ptr() as *const _));
+            gl_try!(gl; gl.VertexAttribPointer(
+                pos_attrib as gl::types::GLuint, 2, gl::FLOAT, 0,
+                2 * mem::size_of::<f32>() as gl::types::GLsizei,
+                ptr::null(),
+            ));
+            gl_try!(gl; gl.EnableVertexAttribArray(pos_attrib as gl::types::GLuint));
+
+            let breath_uniform = gl_try!(gl; gl.GetUniformLocation(self.program_id, b"breath\0".as_ptr() as *const _));
+            gl_try!(gl; gl.Uniform1f(breath_uniform, self.breath));
+
+            let hue_uniform = gl_try!(gl; gl.GetUniformLocation(self.program_id, b"hue\0".as_ptr() as *const _));
+            gl_try!(gl; gl.Uniform1f(hue_uniform, self.hue));
+
+            gl_try!(gl; gl.BindFramebuffer(gl::FRAMEBUFFER, self.framebuffer_id));
+
+            gl_try!(gl; gl.ClearColor(0.0, 0.0, 0.0, 1.0));
+            gl_try!(gl; gl.Clear(gl::COLOR_BUFFER_BIT));
+
+            gl_try!(gl; gl.DrawBuffers(1, [gl::COLOR_ATTACHMENT0].as_ptr()));
+
+            gl_try!(gl; gl.DrawArrays(gl::TRIANGLES, 0,
+                (NUM_SQUARES * NUM_VERTICIES_PER_SQUARE) as i32));
+
+            gl_try!(gl; gl.DeleteBuffers(1, &vb));
+            gl_try!(gl; gl.DeleteVertexArrays(1, &vao));
+        }
+    }
+
+    fn vs_src(&self) -> &[u8] {
+        b"
+#version 100
+precision mediump float;
+
+attribute vec2 position;
+
+// Variables for the Fragment Shader.
+varying vec2 v_position;
+
+void main() {
+    gl_Position = vec4(position, 0.0, 1.0);
+    v_position = position;
+}
+\0"
+    }
+
+    fn fs_src(&self) -> &[u8] {
+        b"
+#version 100
+precision mediump float;
+
+uniform float breath;
+uniform float hue;
+
+// Interpolated from the Vertex Shader.
+varying vec2 v_position;
+
+vec3 hue2rgb(float h) {
+    vec3 rgb = clamp(abs(mod(h * 6.0 + vec3(0.0, 4.0, 2.0), 6.0) - 3.0) - 1.0, 0.0, 1.0);
+    return rgb;
+}
+
+void main() {
+    float dist = length(v_position);
+    if (dist > 1.0) {
+        // Out of bounds.
+        gl_FragColor = vec4(0.0);
+        return;
+    }
+
+    // The center glows brightest, and the glow spreads outwards on each breath.
+    float reach = 0.4 + 0.6 * breath;
+    float glow = clamp(1.0 - dist / reach, 0.0, 1.0);
+    float brightness = 0.1 + 0.4 * breath * glow;
+
+    // Drift the hue slightly with distance so the disk isn't one flat color.
+    vec3 color = hue2rgb(fract(hue + dist * 0.1));
+    gl_FragColor = vec4(color * brightness, 1.0);
+}
+\0"
+    }
+}
+
+fn generate_vertex_data() -> Vec<f32> {
+    let size = 1.0;
+
+    vec![
+        -size, -size,
+        -size, size,
+        size, size,
+        -size, -size,
+        size, -size,
+        size, size,
+    ]
+}
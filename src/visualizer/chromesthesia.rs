@@ -0,0 +1,249 @@
+use audio;
+use gfx;
+use gfx::gl;
+use std::mem;
+use std::ptr;
+use visualizer::visualizer::{SubVisualizer, TUNED_FRAMES_PER_SECOND};
+
+const NUM_SQUARES: usize = 1;
+const NUM_VERTICIES_PER_SQUARE: usize = 6;
+
+const NUM_PITCH_CLASSES: usize = 12;
+
+// How quickly the background follows a change of harmony, per tuned frame.
+const COLOR_SMOOTHING: f32 = 0.1;
+
+// Colors the disk by the notes being played rather than by how loud they are.
+// Pitch classes are laid out around the disk in circle of fifths order (C, G, D, ...), and
+// each gets a hue by its position, so consonant chords blend into neighbouring colors and
+// a key change shifts the whole disk.
+pub struct ChromesthesiaVisualizer {
+    program_id: u32,
+    framebuffer_id: u32,
+    vertex_data: Vec<f32>,
+
+    // Indexed by position around the circle of fifths, not by pitch class.
+    chroma: [f32; NUM_PITCH_CLASSES],
+    harmony_color: [f32; 3],
+    key_hue: f32,
+    key_confidence: f32,
+    amplitude: f32,
+}
+
+impl SubVisualizer for ChromesthesiaVisualizer {
+    fn new() -> ChromesthesiaVisualizer {
+        ChromesthesiaVisualizer {
+            program_id: 0,
+            framebuffer_id: 0,
+            vertex_data: generate_vertex_data(),
+
+            chroma: [0.0; NUM_PITCH_CLASSES],
+            harmony_color: [0.0; 3],
+            key_hue: 0.0,
+            key_confidence: 0.0,
+            amplitude: 0.0,
+        }
+    }
+
+    fn post_setup(&mut self, program_id: u32, framebuffer_id: u32) {
+        self.program_id = program_id;
+        self.framebuffer_id = framebuffer_id;
+    }
+
+    fn update(&mut self, audio_frame: audio::AudioFrame, seconds: f32) {
+        let smoothing = 1.0 - (1.0 - COLOR_SMOOTHING).powf(seconds * TUNED_FRAMES_PER_SECOND);
+
+        let mut target_color = [0.0; 3];
+        let mut total_weight = 0.0;
+        for pitch_class in 0..NUM_PITCH_CLASSES {
+            let value = audio_frame.chroma[pitch_class];
+            let position = fifths_position(pitch_class);
+            self.chroma[position] = value;
+
+            // Square the weights so the strongest notes decide the color.
+            let weight = value * value;
+            let color = hue_to_rgb(position as f32 / NUM_PITCH_CLASSES as f32);
+            for (target, &channel) in target_color.iter_mut().zip(color.iter()) {
+                *target += channel * weight;
+            }
+            total_weight += weight;
+        }
+
+        if total_weight > 0.0 {
+            for (harmony, &target) in self.harmony_color.iter_mut().zip(target_color.iter()) {
+                *harmony += (target / total_weight - *harmony) * smoothing;
+            }
+        }
+
+        self.key_hue = fifths_position(audio_frame.key) as f32 / NUM_PITCH_CLASSES as f32;
+        self.key_confidence = audio_frame.key_confidence;
+
+        let mut amplitude = 0.0;
+        for i in 0..usize::min(20, audio_frame.hundred_hz_buckets.len()) {
+            amplitude += audio_frame.hundred_hz_buckets[i];
+        }
+        self.amplitude = f32::min(1.0, amplitude);
+    }
+
+    fn render_to_texture(&self, gl: &gfx::gl::Gl) {
+        unsafe {
+            gl_try!(gl; gl.UseProgram(self.program_id));
+
+            let mut vb = 0;
+            gl_try!(gl; gl.GenBuffers(1, &mut vb));
+            gl_try!(gl; gl.BindBuffer(gl::ARRAY_BUFFER, vb));
+            gl_try!(gl; gl.BufferData(
+                gl::ARRAY_BUFFER,
+                (self.vertex_data.len() * mem::size_of::<f32>()) as gl::types::GLsizeiptr,
+                self.vertex_data.as_ptr() as *const _,
+                gl::STATIC_DRAW,
+            ));
+
+            let mut vao = 0;
+            gl_try!(gl; gl.GenVertexArrays(1, &mut vao));
+            gl_try!(gl; gl.BindVertexArray(vao));
+
+            let pos_attrib = gl_try!(gl; gl.GetAttribLocation(self.program_id, b"position\0".as_ptr() as *const _));
+            gl_try!(gl; gl.VertexAttribPointer(
+                pos_attrib as gl::types::GLuint, 2, gl::FLOAT, 0,
+                2 * mem::size_of::<f32>() as gl::types::GLsizei,
+                ptr::null(),
+            ));
+            gl_try!(gl; gl.EnableVertexAttribArray(pos_attrib as gl::types::GLuint));
+
+            let chroma_uniform = gl_try!(gl; gl.GetUniformLocation(self.program_id, b"chroma\0".as_ptr() as *const _));
+            gl_try!(gl; gl.Uniform1fv(chroma_uniform, NUM_PITCH_CLASSES as i32, self.chroma.as_ptr()));
+
+            let harmony_color_uniform = gl_try!(gl; gl.GetUniformLocation(self.program_id, b"harmony_color\0".as_ptr() as *const _));
+            gl_try!(gl; gl.Uniform3f(harmony_color_uniform,
+                self.harmony_color[0], self.harmony_color[1], self.harmony_color[2]));
+
+            let key_hue_uniform = gl_try!(gl; gl.GetUniformLocation(self.program_id, b"key_hue\0".as_ptr() as *const _));
+            gl_try!(gl; gl.Uniform1f(key_hue_uniform, self.key_hue));
+
+            let key_confidence_uniform = gl_try!(gl; gl.GetUniformLocation(self.program_id, b"key_confidence\0".as_ptr() as *const _));
+            gl_try!(gl; gl.Uniform1f(key_confidence_uniform, self.key_confidence));
+
+            let amplitude_uniform = gl_try!(gl; gl.GetUniformLocation(self.program_id, b"amplitude\0".as_ptr() as *const _));
+            gl_try!(gl; gl.Uniform1f(amplitude_uniform, self.amplitude));
+
+            gl_try!(gl; gl.BindFramebuffer(gl::FRAMEBUFFER, self.framebuffer_id));
+
+            gl_try!(gl; gl.ClearColor(0.0, 0.0, 0.0, 1.0));
+            gl_try!(gl; gl.Clear(gl::COLOR_BUFFER_BIT));
+
+            gl_try!(gl; gl.DrawBuffers(1, [gl::COLOR_ATTACHMENT0].as_ptr()));
+
+            gl_try!(gl; gl.DrawArrays(gl::TRIANGLES, 0,
+                (NUM_SQUARES * NUM_VERTICIES_PER_SQUARE) as i32));
+
+            gl_try!(gl; gl.DeleteBuffers(1, &vb));
+            gl_try!(gl; gl.DeleteVertexArrays(1, &vao));
+        }
+    }
+
+    fn vs_src(&self) -> &[u8] {
+        b"
+#version 100
+precision mediump float;
+
+attribute vec2 position;
+
+// Variables for the Fragment Shader.
+varying vec2 v_position;
+
+void main() {
+    gl_Position = vec4(position, 0.0, 1.0);
+    v_position = position;
+}
+\0"
+    }
+
+    fn fs_src(&self) -> &[u8] {
+        b"
+#version 100
+precision mediump float;
+
+uniform float chroma[12];
+uniform vec3 harmony_color;
+uniform float key_hue;
+uniform float key_confidence;
+uniform float amplitude;
+
+// Interpolated from the Vertex Shader.
+varying vec2 v_position;
+
+vec3 hue2rgb(float h) {
+    vec3 rgb = clamp(abs(mod(h * 6.0 + vec3(0.0, 4.0, 2.0), 6.0) - 3.0) - 1.0, 0.0, 1.0);
+    return rgb;
+}
+
+void main() {
+    float dist = length(v_position);
+    if (dist > 1.0) {
+        // Out of bounds.
+        gl_FragColor = vec4(0.0);
+        return;
+    }
+
+    // Which of the 12 wedges this pixel is in, clockwise from the top.
+    float angle = atan(v_position.x, v_position.y) / (3.14159 * 2.0);
+    float position = fract(angle + 0.5 / 12.0) * 12.0;
+    int wedge = int(floor(position));
+
+    // Fragment shaders may only index uniform arrays with loop indices.
+    float value = 0.0;
+    for (int i = 0; i < 12; i++) {
+        if (i == wedge) {
+            value = chroma[i];
+        }
+    }
+
+    // Each wedge grows out from the center with the strength of its note.
+    vec3 color = harmony_color * (0.15 + 0.35 * amplitude);
+    float reach = 0.2 + 0.7 * value;
+    if (dist > 0.2 && dist < reach) {
+        float edge = clamp((reach - dist) * 10.0, 0.0, 1.0);
+        color = mix(color, hue2rgb(floor(position) / 12.0) * (0.4 + 0.6 * amplitude), edge);
+    }
+
+    // A ring around the rim in the color of the key, solid once the key is certain.
+    if (dist > 0.94) {
+        color = hue2rgb(key_hue) * clamp(key_confidence, 0.0, 1.0);
+    }
+
+    gl_FragColor = vec4(color, 1.0);
+}
+\0"
+    }
+}
+
+// Position of a pitch class (0 is C) around the circle of fifths.
+fn fifths_position(pitch_class: usize) -> usize {
+    pitch_class * 7 % NUM_PITCH_CLASSES
+}
+
+// Matches hue2rgb in the shaders.
+fn hue_to_rgb(hue: f32) -> [f32; 3] {
+    let mut rgb = [0.0; 3];
+    let offsets = [0.0, 4.0, 2.0];
+    for i in 0..3 {
+        let x = (hue * 6.0 + offsets[i]) % 6.0;
+        rgb[i] = ((x - 3.0).abs() - 1.0).clamp(0.0, 1.0);
+    }
+
+    rgb
+}
+
+fn generate_vertex_data() -> Vec<f32> {
+    let size = 1.0;
+
+    vec![
+        -size, -size,
+        -size, size,
+        size, size,
+        -size, -size,
+        size, -size,
+        size, size,
+    ]
+}
@@ -1,9 +1,10 @@
 use audio;
 use gfx;
 use gfx::gl;
+use std::f32::consts::PI;
 use std::mem;
 use std::ptr;
-use visualizer::visualizer::SubVisualizer;
+use visualizer::visualizer::{SubVisualizer, TUNED_FRAMES_PER_SECOND};
 
 const NUM_SQUARES: usize = 7;
 const NUM_VERTICIES_PER_SQUARE: usize = 6;
@@ -34,11 +35,11 @@ impl SubVisualizer for EqualizerVisualizer {
         self.framebuffer_id = framebuffer_id;
     }
 
-    fn update(&mut self, audio_frame: audio::AudioFrame) {
+    fn update(&mut self, audio_frame: audio::AudioFrame, seconds: f32) {
         self.vertex_data = generate_vertex_data(audio_frame);
-        self.phase += 0.1;
-        if self.phase >= 3.14 * 2.0 {
-            self.phase -= 3.14 * 2.0;
+        self.phase += 0.1 * seconds * TUNED_FRAMES_PER_SECOND;
+        while self.phase >= PI * 2.0 {
+            self.phase -= PI * 2.0;
         }
     }
 
@@ -208,10 +209,10 @@ fn generate_vertex_data(audio_frame: audio::AudioFrame) -> Vec<f32> {
         let mut amplitude = 0.0;
         for j in 0..buckets_per_square {
             let index = i * buckets_per_square + j;
-            if index >= audio_frame.hundred_hz_buckets.len() {
+            if index >= audio_frame.smoothed_buckets.len() {
                 break;
             }
-            amplitude += audio_frame.hundred_hz_buckets[NUM_SQUARES - index - 1];
+            amplitude += audio_frame.smoothed_buckets[NUM_SQUARES - index - 1];
         }
         amplitude = f32::min(1.0, amplitude);
 
@@ -1,7 +1,12 @@
 pub use self::visualizer::Visualizer;
 
 mod visualizer;
+mod ambient;
+mod chromesthesia;
 mod power_circles;
+mod radial_spectrum;
 mod equalizer;
+mod oscilloscope;
 mod smiley;
 mod symmetry;
+mod tuner;
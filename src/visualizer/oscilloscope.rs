@@ -0,0 +1,373 @@
+use audio;
+use gfx;
+use gfx::gl;
+use led_mapper::led_disk_mapper::DISTANCE_BETWEEN_RINGS;
+use std::f32::consts::PI;
+use std::mem;
+use std::ptr;
+use visualizer::visualizer::{SubVisualizer, TUNED_FRAMES_PER_SECOND};
+
+const NUM_SQUARES: usize = 1;
+const NUM_VERTICIES_PER_SQUARE: usize = 6;
+
+// Points on the drawn line. The shaders get them packed four to a vec4, which keeps them
+// within the 16 uniform vectors every GLES2 fragment shader is guaranteed.
+const NUM_POINTS: usize = 64;
+const NUM_POINT_VECTORS: usize = NUM_POINTS / 4;
+
+// The waveform is scaled so its peak reaches this far from the center line or ring.
+const TARGET_AMPLITUDE: f32 = 0.8;
+// Quieter windows than this aren't scaled up any further, so hiss stays small.
+const MIN_PEAK: f32 = 0.01;
+// How quickly the scale follows the peak, per tuned frame. It backs off quickly when the
+// audio gets louder and recovers slowly, so the line doesn't pump with every beat.
+const LOUDER_SMOOTHING: f32 = 0.5;
+const QUIETER_SMOOTHING: f32 = 0.02;
+const HUE_SMOOTHING: f32 = 0.1;
+// How quickly the line fades in silence and comes back, per tuned frame.
+const FADE_PER_FRAME: f32 = 0.05;
+
+// Radius of the ring the waveform wraps around, and how far the waveform can push it.
+const RING_RADIUS: f32 = 0.5;
+const RING_AMPLITUDE: f32 = 0.4;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Mode {
+    // Left to right across the disk, like an oscilloscope screen.
+    Trace,
+    // Wrapped clockwise around a ring from the top, bulging out and in with the signal.
+    Ring,
+}
+
+// Draws the window's waveform as a line, colored by the spectral centroid: red for bass
+// heavy audio through to blue for bright audio.
+struct Oscilloscope {
+    program_id: u32,
+    framebuffer_id: u32,
+    vertex_data: Vec<f32>,
+
+    mode: Mode,
+    points: [f32; NUM_POINTS],
+    scale: f32,
+    hue: f32,
+    brightness: f32,
+}
+
+impl Oscilloscope {
+    fn new(mode: Mode) -> Oscilloscope {
+        Oscilloscope {
+            program_id: 0,
+            framebuffer_id: 0,
+            vertex_data: generate_vertex_data(),
+
+            mode,
+            points: [0.0; NUM_POINTS],
+            scale: TARGET_AMPLITUDE / MIN_PEAK,
+            hue: 0.0,
+            brightness: 0.0,
+        }
+    }
+
+    fn post_setup(&mut self, program_id: u32, framebuffer_id: u32) {
+        self.program_id = program_id;
+        self.framebuffer_id = framebuffer_id;
+    }
+
+    fn update(&mut self, audio_frame: audio::AudioFrame, seconds: f32) {
+        let frames = seconds * TUNED_FRAMES_PER_SECOND;
+
+        let target_scale = TARGET_AMPLITUDE / f32::max(MIN_PEAK, audio_frame.peak);
+        let scale_smoothing = if target_scale < self.scale { LOUDER_SMOOTHING } else { QUIETER_SMOOTHING };
+        self.scale += (target_scale - self.scale) * (1.0 - (1.0 - scale_smoothing).powf(frames));
+
+        let waveform = &audio_frame.waveform;
+        match self.mode {
+            Mode::Trace => {
+                // Start at a rising zero crossing, so a steady tone stands still instead of
+                // scrolling with every window.
+                let start = trigger(waveform, NUM_POINTS);
+                for i in 0..NUM_POINTS {
+                    self.points[i] = waveform.get(start + i).map_or(0.0, |x| x * self.scale);
+                }
+            }
+            Mode::Ring => {
+                // Taper the ends to nothing so the ring closes where they meet at the top.
+                let points = audio::downsample(waveform, NUM_POINTS);
+                for i in 0..NUM_POINTS {
+                    let taper = (PI * (i as f32 + 0.5) / NUM_POINTS as f32).sin();
+                    self.points[i] = points.get(i).map_or(0.0, |x| x * self.scale * taper);
+                }
+            }
+        }
+
+        // 100 Hz is red and 10 kHz is blue, evenly spaced in octaves.
+        let octaves = (audio_frame.spectral_centroid / 100.0).max(1.0).log2() / 100f32.log2();
+        let target_hue = f32::min(1.0, octaves) * 0.66;
+        self.hue += (target_hue - self.hue) * (1.0 - (1.0 - HUE_SMOOTHING).powf(frames));
+
+        if audio_frame.silent {
+            self.brightness = f32::max(0.0, self.brightness - FADE_PER_FRAME * frames);
+        } else {
+            self.brightness = f32::min(1.0, self.brightness + FADE_PER_FRAME * frames);
+        }
+    }
+
+    fn render_to_texture(&self, gl: &gfx::gl::Gl) {
+        unsafe {
+            gl_try!(gl; gl.UseProgram(self.program_id));
+
+            let mut vb = 0;
+            gl_try!(gl; gl.GenBuffers(1, &mut vb));
+            gl_try!(gl; gl.BindBuffer(gl::ARRAY_BUFFER, vb));
+            gl_try!(gl; gl.BufferData(
+                gl::ARRAY_BUFFER,
+                (self.vertex_data.len() * mem::size_of::<f32>()) as gl::types::GLsizeiptr,
+                self.vertex_data.as_ptr() as *const _,
+                gl::STATIC_DRAW,
+            ));
+
+            let mut vao = 0;
+            gl_try!(gl; gl.GenVertexArrays(1, &mut vao));
+            gl_try!(gl; gl.BindVertexArray(vao));
+
+            let pos_attrib = gl_try!(gl; gl.GetAttribLocation(self.program_id, b"position\0".as_ptr() as *const _));
+            gl_try!(gl; gl.VertexAttribPointer(
+                pos_attrib as gl::types::GLuint, 2, gl::FLOAT, 0,
+                2 * mem::size_of::<f32>() as gl::types::GLsizei,
+                ptr::null(),
+            ));
+            gl_try!(gl; gl.EnableVertexAttribArray(pos_attrib as gl::types::GLuint));
+
+            let points_uniform = gl_try!(gl; gl.GetUniformLocation(self.program_id, b"points\0".as_ptr() as *const _));
+            gl_try!(gl; gl.Uniform4fv(points_uniform, NUM_POINT_VECTORS as i32, self.points.as_ptr()));
+
+            let ring_uniform = gl_try!(gl; gl.GetUniformLocation(self.program_id, b"ring\0".as_ptr() as *const _));
+            gl_try!(gl; gl.Uniform1f(ring_uniform, if self.mode == Mode::Ring { 1.0 } else { 0.0 }));
+
+            let ring_radius_uniform = gl_try!(gl; gl.GetUniformLocation(self.program_id, b"ring_radius\0".as_ptr() as *const _));
+            gl_try!(gl; gl.Uniform1f(ring_radius_uniform, RING_RADIUS));
+
+            let ring_amplitude_uniform = gl_try!(gl; gl.GetUniformLocation(self.program_id, b"ring_amplitude\0".as_ptr() as *const _));
+            gl_try!(gl; gl.Uniform1f(ring_amplitude_uniform, RING_AMPLITUDE));
+
+            let line_width_uniform = gl_try!(gl; gl.GetUniformLocation(self.program_id, b"line_width\0".as_ptr() as *const _));
+            gl_try!(gl; gl.Uniform1f(line_width_uniform, DISTANCE_BETWEEN_RINGS));
+
+            let hue_uniform = gl_try!(gl; gl.GetUniformLocation(self.program_id, b"hue\0".as_ptr() as *const _));
+            gl_try!(gl; gl.Uniform1f(hue_uniform, self.hue));
+
+            let brightness_uniform = gl_try!(gl; gl.GetUniformLocation(self.program_id, b"brightness\0".as_ptr() as *const _));
+            gl_try!(gl; gl.Uniform1f(brightness_uniform, self.brightness));
+
+            gl_try!(gl; gl.BindFramebuffer(gl::FRAMEBUFFER, self.framebuffer_id));
+
+            gl_try!(gl; gl.ClearColor(0.0, 0.0, 0.0, 1.0));
+            gl_try!(gl; gl.Clear(gl::COLOR_BUFFER_BIT));
+
+            gl_try!(gl; gl.DrawBuffers(1, [gl::COLOR_ATTACHMENT0].as_ptr()));
+
+            gl_try!(gl; gl.DrawArrays(gl::TRIANGLES, 0,
+                (NUM_SQUARES * NUM_VERTICIES_PER_SQUARE) as i32));
+
+            gl_try!(gl; gl.DeleteBuffers(1, &vb));
+            gl_try!(gl; gl.DeleteVertexArrays(1, &vao));
+        }
+    }
+}
+
+// The waveform as a line across the disk.
+pub struct OscilloscopeVisualizer {
+    oscilloscope: Oscilloscope,
+}
+
+impl SubVisualizer for OscilloscopeVisualizer {
+    fn new() -> OscilloscopeVisualizer {
+        OscilloscopeVisualizer {
+            oscilloscope: Oscilloscope::new(Mode::Trace),
+        }
+    }
+
+    fn post_setup(&mut self, program_id: u32, framebuffer_id: u32) {
+        self.oscilloscope.post_setup(program_id, framebuffer_id);
+    }
+
+    fn update(&mut self, audio_frame: audio::AudioFrame, seconds: f32) {
+        self.oscilloscope.update(audio_frame, seconds);
+    }
+
+    fn render_to_texture(&self, gl: &gfx::gl::Gl) {
+        self.oscilloscope.render_to_texture(gl);
+    }
+
+    fn vs_src(&self) -> &[u8] {
+        VS_SRC
+    }
+
+    fn fs_src(&self) -> &[u8] {
+        FS_SRC
+    }
+}
+
+// The waveform wrapped around a ring, so the signal deforms its radius.
+pub struct RingOscilloscopeVisualizer {
+    oscilloscope: Oscilloscope,
+}
+
+impl SubVisualizer for RingOscilloscopeVisualizer {
+    fn new() -> RingOscilloscopeVisualizer {
+        RingOscilloscopeVisualizer {
+            oscilloscope: Oscilloscope::new(Mode::Ring),
+        }
+    }
+
+    fn post_setup(&mut self, program_id: u32, framebuffer_id: u32) {
+        self.oscilloscope.post_setup(program_id, framebuffer_id);
+    }
+
+    fn update(&mut self, audio_frame: audio::AudioFrame, seconds: f32) {
+        self.oscilloscope.update(audio_frame, seconds);
+    }
+
+    fn render_to_texture(&self, gl: &gfx::gl::Gl) {
+        self.oscilloscope.render_to_texture(gl);
+    }
+
+    fn vs_src(&self) -> &[u8] {
+        VS_SRC
+    }
+
+    fn fs_src(&self) -> &[u8] {
+        FS_SRC
+    }
+}
+
+const VS_SRC: &[u8] = b"
+#version 100
+precision mediump float;
+
+attribute vec2 position;
+
+// Variables for the Fragment Shader.
+varying vec2 v_position;
+
+void main() {
+    gl_Position = vec4(position, 0.0, 1.0);
+    v_position = position;
+}
+\0";
+
+// NUM_POINTS and NUM_POINT_VECTORS must match the constants above.
+const FS_SRC: &[u8] = b"
+#version 100
+precision mediump float;
+
+#define PI 3.1415926535897932384626433832795
+#define NUM_POINTS 64.0
+#define NUM_POINT_VECTORS 16
+
+uniform vec4 points[NUM_POINT_VECTORS];
+uniform float ring;
+uniform float ring_radius;
+uniform float ring_amplitude;
+uniform float line_width;
+uniform float hue;
+uniform float brightness;
+
+// Interpolated from the Vertex Shader.
+varying vec2 v_position;
+
+vec3 hue2rgb(float h) {
+    vec3 rgb = clamp(abs(mod(h * 6.0 + vec3(0.0, 4.0, 2.0), 6.0) - 3.0) - 1.0, 0.0, 1.0);
+    return rgb;
+}
+
+vec2 point_position(float index, float value) {
+    if (ring > 0.5) {
+        float angle = index / NUM_POINTS * PI * 2.0;
+        return vec2(sin(angle), cos(angle)) * (ring_radius + value * ring_amplitude);
+    }
+    return vec2(index / (NUM_POINTS - 1.0) * 2.0 - 1.0, value);
+}
+
+float segment_distance(vec2 p, vec2 a, vec2 b) {
+    vec2 ab = b - a;
+    float t = clamp(dot(p - a, ab) / max(dot(ab, ab), 0.000001), 0.0, 1.0);
+    return length(p - a - ab * t);
+}
+
+void main() {
+    if (length(v_position) > 1.0) {
+        // Out of bounds.
+        gl_FragColor = vec4(0.0);
+        return;
+    }
+
+    // Distance to the nearest segment of the line. Fragment shaders may only index uniform
+    // arrays with loop indices.
+    vec2 first = point_position(0.0, points[0].x);
+    vec2 previous = first;
+    float nearest = 10.0;
+    for (int i = 0; i < NUM_POINT_VECTORS; i++) {
+        for (int j = 0; j < 4; j++) {
+            vec2 current = point_position(float(i * 4 + j), points[i][j]);
+            nearest = min(nearest, segment_distance(v_position, previous, current));
+            previous = current;
+        }
+    }
+    if (ring > 0.5) {
+        nearest = min(nearest, segment_distance(v_position, previous, first));
+    }
+
+    float line = clamp(1.0 - nearest / line_width, 0.0, 1.0);
+    gl_FragColor = vec4(hue2rgb(hue) * line * brightness, 1.0);
+}
+\0";
+
+// Index of the first rising zero crossing that leaves `length` samples after it, or 0.
+fn trigger(waveform: &[f32], length: usize) -> usize {
+    let last_start = waveform.len().saturating_sub(length);
+    (1..last_start + 1)
+        .find(|&i| waveform[i - 1] < 0.0 && waveform[i] >= 0.0)
+        .unwrap_or(0)
+}
+
+fn generate_vertex_data() -> Vec<f32> {
+    let size = 1.0;
+
+    vec![
+        -size, -size,
+        -size, size,
+        size, size,
+        -size, -size,
+        size, -size,
+        size, size,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trigger_finds_the_first_rising_zero_crossing() {
+        let waveform = [0.5, -0.5, -0.25, 0.0, 0.5, -0.5, 0.5, 0.0];
+        assert_eq!(trigger(&waveform, 4), 3);
+        assert_eq!(trigger(&waveform, 2), 3);
+    }
+
+    #[test]
+    fn trigger_leaves_room_for_the_trace() {
+        let waveform = [0.5, 0.5, -0.5, 0.5, 0.5, 0.5];
+        // The only crossing is at 3, which leaves 3 samples.
+        assert_eq!(trigger(&waveform, 3), 3);
+        assert_eq!(trigger(&waveform, 4), 0);
+    }
+
+    #[test]
+    fn trigger_falls_back_to_the_start() {
+        assert_eq!(trigger(&[0.5; 8], 4), 0);
+        assert_eq!(trigger(&[-0.5; 8], 4), 0);
+        assert_eq!(trigger(&[], 4), 0);
+        assert_eq!(trigger(&[-0.5, 0.5], 4), 0);
+    }
+}
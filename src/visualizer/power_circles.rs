@@ -30,10 +30,10 @@ impl SubVisualizer for PowerCirclesVisualizer {
         self.framebuffer_id = framebuffer_id;
     }
 
-    fn update(&mut self, audio_frame: audio::AudioFrame) {
-        let low = audio_frame.low_power;
-        let mid = audio_frame.mid_power;
-        let high = audio_frame.high_power;
+    fn update(&mut self, audio_frame: audio::AudioFrame, _seconds: f32) {
+        let low = audio_frame.smoothed_low_power;
+        let mid = audio_frame.smoothed_mid_power;
+        let high = audio_frame.smoothed_high_power;
 
         self.vertex_data = generate_vertex_data(low, mid, high);
     }
@@ -0,0 +1,206 @@
+use audio;
+use gfx;
+use gfx::gl;
+use led_mapper::led_disk_mapper::{DISTANCE_BETWEEN_RINGS, NUM_RINGS};
+use std::mem;
+use std::ptr;
+use visualizer::visualizer::SubVisualizer;
+
+const NUM_SQUARES: usize = 1;
+const NUM_VERTICIES_PER_SQUARE: usize = 6;
+
+// A spectrum analyzer laid out on the disk's rings. The bands are split into one group per
+// ring, bass in the center and treble on the outside, and each ring fills clockwise from
+// the top with its group's level. A bright marker sits at each ring's recent peak.
+pub struct RadialSpectrumVisualizer {
+    program_id: u32,
+    framebuffer_id: u32,
+    vertex_data: Vec<f32>,
+
+    // The level and then the peak of each ring, from 0 to 1, innermost first.
+    rings: [f32; NUM_RINGS * 2],
+}
+
+impl SubVisualizer for RadialSpectrumVisualizer {
+    fn new() -> RadialSpectrumVisualizer {
+        RadialSpectrumVisualizer {
+            program_id: 0,
+            framebuffer_id: 0,
+            vertex_data: generate_vertex_data(),
+
+            rings: [0.0; NUM_RINGS * 2],
+        }
+    }
+
+    fn post_setup(&mut self, program_id: u32, framebuffer_id: u32) {
+        self.program_id = program_id;
+        self.framebuffer_id = framebuffer_id;
+    }
+
+    fn update(&mut self, audio_frame: audio::AudioFrame, _seconds: f32) {
+        // The envelope already smooths the bands and holds their peaks.
+        let band_count = audio_frame.smoothed_bands.len();
+        for ring in 0..NUM_RINGS {
+            // With fewer bands than rings, neighbouring rings share a band.
+            let start = ring * band_count / NUM_RINGS;
+            let end = usize::min(band_count, usize::max(start + 1, (ring + 1) * band_count / NUM_RINGS));
+            if start >= end {
+                self.rings[ring * 2] = 0.0;
+                self.rings[ring * 2 + 1] = 0.0;
+                continue;
+            }
+
+            let level = audio_frame.smoothed_bands[start..end].iter().sum::<f32>() / (end - start) as f32;
+            let peak = audio_frame.peak_bands[start..end].iter().fold(0.0, |peak, &x| f32::max(peak, x));
+            self.rings[ring * 2] = level.clamp(0.0, 1.0);
+            self.rings[ring * 2 + 1] = peak.clamp(0.0, 1.0);
+        }
+    }
+
+    fn render_to_texture(&self, gl: &gfx::gl::Gl) {
+        unsafe {
+            gl_try!(gl; gl.UseProgram(self.program_id));
+
+            let mut vb = 0;
+            gl_try!(gl; gl.GenBuffers(1, &mut vb));
+            gl_try!(gl; gl.BindBuffer(gl::ARRAY_BUFFER, vb));
+            gl_try!(gl; gl.BufferData(
+                gl::ARRAY_BUFFER,
+                (self.vertex_data.len() * mem::size_of::<f32>()) as gl::types::GLsizeiptr,
+                self.vertex_data.as_ptr() as *const _,
+                gl::STATIC_DRAW,
+            ));
+
+            let mut vao = 0;
+            gl_try!(gl; gl.GenVertexArrays(1, &mut vao));
+            gl_try!(gl; gl.BindVertexArray(vao));
+
+            let pos_attrib = gl_try!(gl; gl.GetAttribLocation(self.program_id, b"position\0".as_ptr() as *const _));
+            gl_try!(gl; gl.VertexAttribPointer(
+                pos_attrib as gl::types::GLuint, 2, gl::FLOAT, 0,
+                2 * mem::size_of::<f32>() as gl::types::GLsizei,
+                ptr::null(),
+            ));
+            gl_try!(gl; gl.EnableVertexAttribArray(pos_attrib as gl::types::GLuint));
+
+            let rings_uniform = gl_try!(gl; gl.GetUniformLocation(self.program_id, b"rings\0".as_ptr() as *const _));
+            gl_try!(gl; gl.Uniform2fv(rings_uniform, NUM_RINGS as i32, self.rings.as_ptr()));
+
+            let ring_spacing_uniform = gl_try!(gl; gl.GetUniformLocation(self.program_id, b"ring_spacing\0".as_ptr() as *const _));
+            gl_try!(gl; gl.Uniform1f(ring_spacing_uniform, DISTANCE_BETWEEN_RINGS));
+
+            gl_try!(gl; gl.BindFramebuffer(gl::FRAMEBUFFER, self.framebuffer_id));
+
+            gl_try!(gl; gl.ClearColor(0.0, 0.0, 0.0, 1.0));
+            gl_try!(gl; gl.Clear(gl::COLOR_BUFFER_BIT));
+
+            gl_try!(gl; gl.DrawBuffers(1, [gl::COLOR_ATTACHMENT0].as_ptr()));
+
+            gl_try!(gl; gl.DrawArrays(gl::TRIANGLES, 0,
+                (NUM_SQUARES * NUM_VERTICIES_PER_SQUARE) as i32));
+
+            gl_try!(gl; gl.DeleteBuffers(1, &vb));
+            gl_try!(gl; gl.DeleteVertexArrays(1, &vao));
+        }
+    }
+
+    fn vs_src(&self) -> &[u8] {
+        b"
+#version 100
+precision mediump float;
+
+attribute vec2 position;
+
+// Variables for the Fragment Shader.
+varying vec2 v_position;
+
+void main() {
+    gl_Position = vec4(position, 0.0, 1.0);
+    v_position = position;
+}
+\0"
+    }
+
+    fn fs_src(&self) -> &[u8] {
+        b"
+#version 100
+precision mediump float;
+
+#define PI 3.1415926535897932384626433832795
+// Must match NUM_RINGS in the LED disk mapper.
+#define NUM_RINGS 10
+
+// The level and peak of each ring, innermost first.
+uniform vec2 rings[NUM_RINGS];
+uniform float ring_spacing;
+
+// Interpolated from the Vertex Shader.
+varying vec2 v_position;
+
+vec3 hue2rgb(float h) {
+    vec3 rgb = clamp(abs(mod(h * 6.0 + vec3(0.0, 4.0, 2.0), 6.0) - 3.0) - 1.0, 0.0, 1.0);
+    return rgb;
+}
+
+void main() {
+    float dist = length(v_position);
+    int ring_index = int(floor(dist / ring_spacing + 0.5));
+    if (dist > 1.0 || ring_index >= NUM_RINGS) {
+        // Out of bounds.
+        gl_FragColor = vec4(0.0);
+        return;
+    }
+
+    // Fragment shaders may only index uniform arrays with loop indices.
+    vec2 ring = vec2(0.0);
+    for (int i = 0; i < NUM_RINGS; i++) {
+        if (i == ring_index) {
+            ring = rings[i];
+        }
+    }
+    float level = ring.x;
+    float peak = ring.y;
+
+    // Red bass in the center through to violet treble on the outside.
+    vec3 hue_color = hue2rgb(float(ring_index) / float(NUM_RINGS) * 0.8);
+
+    if (ring_index == 0) {
+        // The center is a single LED, so it only gets brighter.
+        gl_FragColor = vec4(hue_color * level, 1.0);
+        return;
+    }
+
+    // Position around the ring, clockwise from the top, from 0 to 1.
+    float angle = fract(atan(v_position.x, v_position.y) / (PI * 2.0));
+
+    // A dim track shows the shape of each ring, and the level fills it in.
+    vec3 color = hue_color * 0.04;
+    if (angle < level) {
+        color = hue_color * (0.3 + 0.7 * level);
+    }
+
+    // The peak marker is about one LED wide on every ring, measured along the ring.
+    float from_peak = abs(angle - peak);
+    from_peak = min(from_peak, 1.0 - from_peak) * PI * 2.0 * dist;
+    if (peak > 0.01 && from_peak < ring_spacing * 0.6) {
+        color = mix(hue_color, vec3(1.0), 0.6);
+    }
+
+    gl_FragColor = vec4(color, 1.0);
+}
+\0"
+    }
+}
+
+fn generate_vertex_data() -> Vec<f32> {
+    let size = 1.0;
+
+    vec![
+        -size, -size,
+        -size, size,
+        size, size,
+        -size, -size,
+        size, -size,
+        size, size,
+    ]
+}
@@ -1,9 +1,10 @@
 use audio;
 use gfx;
 use gfx::gl;
+use std::f32::consts::PI;
 use std::mem;
 use std::ptr;
-use visualizer::visualizer::SubVisualizer;
+use visualizer::visualizer::{SubVisualizer, TUNED_FRAMES_PER_SECOND};
 
 const NUM_SQUARES: usize = 1;
 const NUM_VERTICIES_PER_SQUARE: usize = 6;
@@ -34,20 +35,20 @@ impl SubVisualizer for SmileyVisualizer {
         self.framebuffer_id = framebuffer_id;
     }
 
-    fn update(&mut self, audio_frame: audio::AudioFrame) {
+    fn update(&mut self, audio_frame: audio::AudioFrame, seconds: f32) {
         self.vertex_data = generate_vertex_data();
 
         // Sum the 1000-2000hz amplitudes.
         self.amplitude = 0.0;
-        for i in 4..20 {
+        for i in 4..usize::min(20, audio_frame.hundred_hz_buckets.len()) {
             self.amplitude += audio_frame.hundred_hz_buckets[i];
         }
         self.amplitude /= 1.0;
         self.amplitude = f32::min(1.0, self.amplitude);
 
-        self.phase += 0.1;
-        if self.phase >= 3.14 * 2.0 {
-            self.phase -= 3.14 * 2.0;
+        self.phase += 0.1 * seconds * TUNED_FRAMES_PER_SECOND;
+        while self.phase >= PI * 2.0 {
+            self.phase -= PI * 2.0;
         }
     }
 
@@ -3,6 +3,7 @@ use gfx;
 use gfx::gl;
 use std::mem;
 use std::ptr;
+use visualizer::visualizer::TUNED_FRAMES_PER_SECOND;
 
 const NUM_SQUARES: usize = 1;
 const NUM_VERTICIES_PER_SQUARE: usize = 6;
@@ -14,8 +15,11 @@ pub struct SymmetryVisualizer {
     framebuffer_id: u32,
     vertex_data: Vec<f32>,
 
-    phase: f32,
-    speed: f32,
+    // Each half of the disk has its own phase and speed, following its stereo channel.
+    left_phase: f32,
+    right_phase: f32,
+    left_speed: f32,
+    right_speed: f32,
 }
 
 const MAX_SPEED: f32 = 0.2;
@@ -27,8 +31,10 @@ impl SymmetryVisualizer {
             framebuffer_id: 0,
             vertex_data: generate_vertex_data(),
 
-            phase: 0.0,
-            speed: 0.0,
+            left_phase: 0.0,
+            right_phase: 0.0,
+            left_speed: 0.0,
+            right_speed: 0.0,
         }
     }
 
@@ -37,7 +43,7 @@ impl SymmetryVisualizer {
         self.framebuffer_id = framebuffer_id;
     }
 
-    pub fn update(&mut self, audio_frame: audio::AudioFrame) {
+    pub fn update(&mut self, audio_frame: audio::AudioFrame, seconds: f32) {
         let mut amplitude = 0.0;
         for i in 0..20 {
             if i >= audio_frame.hundred_hz_buckets.len() {
@@ -48,8 +54,22 @@ impl SymmetryVisualizer {
         amplitude /= 2.0;
         amplitude = f32::min(1.0, amplitude);
 
-        self.speed = amplitude * MAX_SPEED;
-        self.phase += self.speed;
+        // Share the speed out by how loud each channel is, with the louder one at full speed.
+        // Mono sources copy their bands into both channels, so both halves match.
+        let left_energy: f32 = audio_frame.left_bands.iter().sum();
+        let right_energy: f32 = audio_frame.right_bands.iter().sum();
+        let loudest = f32::max(left_energy, right_energy);
+        let (left_share, right_share) = if loudest > 0.0 {
+            (left_energy / loudest, right_energy / loudest)
+        } else {
+            (1.0, 1.0)
+        };
+
+        let frames = seconds * TUNED_FRAMES_PER_SECOND;
+        self.left_speed = amplitude * left_share * MAX_SPEED;
+        self.right_speed = amplitude * right_share * MAX_SPEED;
+        self.left_phase += self.left_speed * frames;
+        self.right_phase += self.right_speed * frames;
     }
 
     pub fn render_to_texture(&self, gl: &gfx::gl::Gl) {
@@ -78,11 +98,17 @@ impl SymmetryVisualizer {
             ));
             gl_try!(gl; gl.EnableVertexAttribArray(pos_attrib as gl::types::GLuint));
 
-            let phase_uniform = gl_try!(gl; gl.GetUniformLocation(self.program_id, b"phase\0".as_ptr() as *const _));
-            gl_try!(gl; gl.Uniform1f(phase_uniform, self.phase));
+            let left_phase_uniform = gl_try!(gl; gl.GetUniformLocation(self.program_id, b"left_phase\0".as_ptr() as *const _));
+            gl_try!(gl; gl.Uniform1f(left_phase_uniform, self.left_phase));
 
-            let speed_uniform = gl_try!(gl; gl.GetUniformLocation(self.program_id, b"speed\0".as_ptr() as *const _));
-            gl_try!(gl; gl.Uniform1f(speed_uniform, self.speed / MAX_SPEED));
+            let right_phase_uniform = gl_try!(gl; gl.GetUniformLocation(self.program_id, b"right_phase\0".as_ptr() as *const _));
+            gl_try!(gl; gl.Uniform1f(right_phase_uniform, self.right_phase));
+
+            let left_speed_uniform = gl_try!(gl; gl.GetUniformLocation(self.program_id, b"left_speed\0".as_ptr() as *const _));
+            gl_try!(gl; gl.Uniform1f(left_speed_uniform, self.left_speed / MAX_SPEED));
+
+            let right_speed_uniform = gl_try!(gl; gl.GetUniformLocation(self.program_id, b"right_speed\0".as_ptr() as *const _));
+            gl_try!(gl; gl.Uniform1f(right_speed_uniform, self.right_speed / MAX_SPEED));
 
             gl_try!(gl; gl.BindFramebuffer(gl::FRAMEBUFFER, self.framebuffer_id));
 
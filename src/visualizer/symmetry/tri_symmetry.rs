@@ -18,8 +18,8 @@ impl SubVisualizer for TriSymmetryVisualizer {
         self.symmetry_visualizer.post_setup(program_id, framebuffer_id);
     }
 
-    fn update(&mut self, audio_frame: audio::AudioFrame) {
-        self.symmetry_visualizer.update(audio_frame);
+    fn update(&mut self, audio_frame: audio::AudioFrame, seconds: f32) {
+        self.symmetry_visualizer.update(audio_frame, seconds);
     }
 
     fn render_to_texture(&self, gl: &gfx::gl::Gl) {
@@ -58,8 +58,11 @@ precision mediump float;
 #define PI 3.1415926535897932384626433832795
 #define TAU PI * 2.0
 
-uniform float phase;
-uniform float speed;
+// Each half of the disk follows its own stereo channel. Mono sources drive both the same.
+uniform float left_phase;
+uniform float right_phase;
+uniform float left_speed;
+uniform float right_speed;
 
 // Interpolated from the Vertex Shader.
 varying vec2 v_position;
@@ -151,6 +154,9 @@ void main() {
         // Out of bounds.
         gl_FragColor = vec4(0.0);
     } else {
+        float phase = v_position.x < 0.0 ? left_phase : right_phase;
+        float speed = v_position.x < 0.0 ? left_speed : right_speed;
+
         vec2 p = v_position;
         p *= rotation(phase);
         vec2 p1 = p;
@@ -0,0 +1,226 @@
+use audio;
+use gfx;
+use gfx::gl;
+use led_mapper::led_disk_mapper::{DISTANCE_BETWEEN_RINGS, NUM_RINGS};
+use std::mem;
+use std::ptr;
+use visualizer::visualizer::{SubVisualizer, TUNED_FRAMES_PER_SECOND};
+
+const NUM_SQUARES: usize = 1;
+const NUM_VERTICIES_PER_SQUARE: usize = 6;
+
+// The outermost ring of LEDs, where the note is drawn.
+const OUTER_RING_RADIUS: f32 = (NUM_RINGS - 1) as f32 * DISTANCE_BETWEEN_RINGS;
+
+// Within this many cents of a note counts as in tune.
+const IN_TUNE_CENTS: f32 = 5.0;
+
+// How quickly the needle follows the pitch, per tuned frame. Voices waver, and a needle that
+// jumps with every frame is hard to read.
+const NEEDLE_SMOOTHING: f32 = 0.3;
+// How quickly the needle fades once the pitch is lost, per tuned frame.
+const FADE_PER_FRAME: f32 = 0.05;
+
+// A chromatic tuner. The 12 notes sit around the outer ring like a clock, with C at the
+// top, and a needle on the ring points at the note being sung, between marks when it's
+// off. The center shows how far off: green when in tune, red when sharp, blue when flat.
+pub struct TunerVisualizer {
+    program_id: u32,
+    framebuffer_id: u32,
+    vertex_data: Vec<f32>,
+
+    // Position around the ring in notes, from 0 (C) up to 12.
+    needle: f32,
+    cents: f32,
+    brightness: f32,
+}
+
+impl SubVisualizer for TunerVisualizer {
+    fn new() -> TunerVisualizer {
+        TunerVisualizer {
+            program_id: 0,
+            framebuffer_id: 0,
+            vertex_data: generate_vertex_data(),
+
+            needle: 0.0,
+            cents: 0.0,
+            brightness: 0.0,
+        }
+    }
+
+    fn post_setup(&mut self, program_id: u32, framebuffer_id: u32) {
+        self.program_id = program_id;
+        self.framebuffer_id = framebuffer_id;
+    }
+
+    fn update(&mut self, audio_frame: audio::AudioFrame, seconds: f32) {
+        let frames = seconds * TUNED_FRAMES_PER_SECOND;
+        let smoothing = 1.0 - (1.0 - NEEDLE_SMOOTHING).powf(frames);
+
+        if audio_frame.pitch_hz <= 0.0 {
+            self.brightness = f32::max(0.0, self.brightness - FADE_PER_FRAME * frames);
+            return;
+        }
+
+        let target = ((audio_frame.pitch_note % 12 + 12) % 12) as f32 + audio_frame.pitch_cents / 100.0;
+        if self.brightness <= 0.0 {
+            self.needle = target;
+        } else {
+            // Take the short way around the ring, e.g. from B to C.
+            let mut delta = target - self.needle;
+            delta -= 12.0 * (delta / 12.0).round();
+            self.needle += delta * smoothing;
+        }
+        self.needle -= 12.0 * (self.needle / 12.0).floor();
+
+        self.cents += (audio_frame.pitch_cents - self.cents) * smoothing;
+        self.brightness = audio_frame.pitch_confidence;
+    }
+
+    fn render_to_texture(&self, gl: &gfx::gl::Gl) {
+        unsafe {
+            gl_try!(gl; gl.UseProgram(self.program_id));
+
+            let mut vb = 0;
+            gl_try!(gl; gl.GenBuffers(1, &mut vb));
+            gl_try!(gl; gl.BindBuffer(gl::ARRAY_BUFFER, vb));
+            gl_try!(gl; gl.BufferData(
+                gl::ARRAY_BUFFER,
+                (self.vertex_data.len() * mem::size_of::<f32>()) as gl::types::GLsizeiptr,
+                self.vertex_data.as_ptr() as *const _,
+                gl::STATIC_DRAW,
+            ));
+
+            let mut vao = 0;
+            gl_try!(gl; gl.GenVertexArrays(1, &mut vao));
+            gl_try!(gl; gl.BindVertexArray(vao));
+
+            let pos_attrib = gl_try!(gl; gl.GetAttribLocation(self.program_id, b"position\0".as_ptr() as *const _));
+            gl_try!(gl; gl.VertexAttribPointer(
+                pos_attrib as gl::types::GLuint, 2, gl::FLOAT, 0,
+                2 * mem::size_of::<f32>() as gl::types::GLsizei,
+                ptr::null(),
+            ));
+            gl_try!(gl; gl.EnableVertexAttribArray(pos_attrib as gl::types::GLuint));
+
+            let needle_uniform = gl_try!(gl; gl.GetUniformLocation(self.program_id, b"needle\0".as_ptr() as *const _));
+            gl_try!(gl; gl.Uniform1f(needle_uniform, self.needle));
+
+            let cents_uniform = gl_try!(gl; gl.GetUniformLocation(self.program_id, b"cents\0".as_ptr() as *const _));
+            gl_try!(gl; gl.Uniform1f(cents_uniform, self.cents));
+
+            let in_tune_cents_uniform = gl_try!(gl; gl.GetUniformLocation(self.program_id, b"in_tune_cents\0".as_ptr() as *const _));
+            gl_try!(gl; gl.Uniform1f(in_tune_cents_uniform, IN_TUNE_CENTS));
+
+            let brightness_uniform = gl_try!(gl; gl.GetUniformLocation(self.program_id, b"brightness\0".as_ptr() as *const _));
+            gl_try!(gl; gl.Uniform1f(brightness_uniform, self.brightness));
+
+            let ring_radius_uniform = gl_try!(gl; gl.GetUniformLocation(self.program_id, b"ring_radius\0".as_ptr() as *const _));
+            gl_try!(gl; gl.Uniform1f(ring_radius_uniform, OUTER_RING_RADIUS));
+
+            let ring_width_uniform = gl_try!(gl; gl.GetUniformLocation(self.program_id, b"ring_width\0".as_ptr() as *const _));
+            gl_try!(gl; gl.Uniform1f(ring_width_uniform, DISTANCE_BETWEEN_RINGS));
+
+            gl_try!(gl; gl.BindFramebuffer(gl::FRAMEBUFFER, self.framebuffer_id));
+
+            gl_try!(gl; gl.ClearColor(0.0, 0.0, 0.0, 1.0));
+            gl_try!(gl; gl.Clear(gl::COLOR_BUFFER_BIT));
+
+            gl_try!(gl; gl.DrawBuffers(1, [gl::COLOR_ATTACHMENT0].as_ptr()));
+
+            gl_try!(gl; gl.DrawArrays(gl::TRIANGLES, 0,
+                (NUM_SQUARES * NUM_VERTICIES_PER_SQUARE) as i32));
+
+            gl_try!(gl; gl.DeleteBuffers(1, &vb));
+            gl_try!(gl; gl.DeleteVertexArrays(1, &vao));
+        }
+    }
+
+    fn vs_src(&self) -> &[u8] {
+        b"
+#version 100
+precision mediump float;
+
+attribute vec2 position;
+
+// Variables for the Fragment Shader.
+varying vec2 v_position;
+
+void main() {
+    gl_Position = vec4(position, 0.0, 1.0);
+    v_position = position;
+}
+\0"
+    }
+
+    fn fs_src(&self) -> &[u8] {
+        b"
+#version 100
+precision mediump float;
+
+uniform float needle;
+uniform float cents;
+uniform float in_tune_cents;
+uniform float brightness;
+uniform float ring_radius;
+uniform float ring_width;
+
+// Interpolated from the Vertex Shader.
+varying vec2 v_position;
+
+void main() {
+    float dist = length(v_position);
+    if (dist > 1.0) {
+        // Out of bounds.
+        gl_FragColor = vec4(0.0);
+        return;
+    }
+
+    // Green when in tune, fading to red when sharp and blue when flat.
+    float off = clamp(abs(cents) / 50.0, 0.0, 1.0);
+    vec3 off_color = cents > 0.0 ? vec3(1.0, 0.1, 0.0) : vec3(0.0, 0.2, 1.0);
+    vec3 tune_color = abs(cents) <= in_tune_cents ? vec3(0.0, 1.0, 0.2) : mix(vec3(0.6, 0.8, 0.0), off_color, off);
+
+    vec3 color = vec3(0.0);
+
+    if (abs(dist - ring_radius) < ring_width * 0.5) {
+        // Position around the ring in notes, clockwise from the top.
+        float position = fract(atan(v_position.x, v_position.y) / (3.14159 * 2.0)) * 12.0;
+
+        // Dim marks at each note, with C a little brighter so the ring can be read.
+        float mark = abs(position - floor(position + 0.5));
+        if (mark < 0.1) {
+            color = vec3(floor(position + 0.5) == 0.0 || floor(position + 0.5) == 12.0 ? 0.15 : 0.05);
+        }
+
+        // The needle, measured the short way around the ring.
+        float distance_to_needle = abs(position - needle);
+        distance_to_needle = min(distance_to_needle, 12.0 - distance_to_needle);
+        float needle_glow = clamp(1.0 - distance_to_needle / 0.4, 0.0, 1.0);
+        color = max(color, tune_color * needle_glow * brightness);
+    } else if (dist < ring_radius - ring_width) {
+        // The center fills in as the note settles into tune.
+        float fill = (1.0 - off) * (ring_radius - ring_width);
+        if (dist < fill) {
+            color = tune_color * brightness * 0.5;
+        }
+    }
+
+    gl_FragColor = vec4(color, 1.0);
+}
+\0"
+    }
+}
+
+fn generate_vertex_data() -> Vec<f32> {
+    let size = 1.0;
+
+    vec![
+        -size, -size,
+        -size, size,
+        size, size,
+        -size, -size,
+        size, -size,
+        size, size,
+    ]
+}
@@ -4,18 +4,24 @@ use gfx::gl;
 use std::mem;
 use std::ptr;
 use std::time;
+use visualizer::ambient::AmbientVisualizer;
+use visualizer::chromesthesia::ChromesthesiaVisualizer;
 use visualizer::equalizer::EqualizerVisualizer;
+use visualizer::oscilloscope::{OscilloscopeVisualizer, RingOscilloscopeVisualizer};
 use visualizer::power_circles::PowerCirclesVisualizer;
+use visualizer::radial_spectrum::RadialSpectrumVisualizer;
 use visualizer::smiley::SmileyVisualizer;
 use visualizer::symmetry::BiSymmetryVisualizer;
 use visualizer::symmetry::TriSymmetryVisualizer;
 use visualizer::symmetry::QuadSymmetryVisualizer;
 use visualizer::symmetry::PentaSymmetryVisualizer;
+use visualizer::tuner::TunerVisualizer;
 
 pub trait SubVisualizer {
     fn new() -> Self where Self: Sized;
     fn post_setup(&mut self, program_id: u32, framebuffer_id: u32);
-    fn update(&mut self, audio_frame: audio::AudioFrame);
+    // `seconds` is the time since the previous update.
+    fn update(&mut self, audio_frame: audio::AudioFrame, seconds: f32);
     fn render_to_texture(&self, gl: &gfx::gl::Gl);
     fn vs_src(&self) -> &[u8];
     fn fs_src(&self) -> &[u8];
@@ -23,6 +29,12 @@ pub trait SubVisualizer {
 
 const TIME_PER_VISUALIZER: time::Duration = time::Duration::from_secs(30);
 
+// Animations were tuned for one update per audio frame at the original settings (24000
+// samples per second in windows of 1024 with no overlap). Per-update steps are scaled by
+// how many of those frames have passed, so animations run at the same speed at any frame
+// rate.
+pub const TUNED_FRAMES_PER_SECOND: f32 = 23.4375;
+
 pub struct Visualizer {
     texture_id: u32,
 
@@ -33,15 +45,26 @@ pub struct Visualizer {
     trisymmetry_visualizer: TriSymmetryVisualizer,
     quadsymmetry_visualizer: QuadSymmetryVisualizer,
     pentasymmetry_visualizer: PentaSymmetryVisualizer,
+    chromesthesia_visualizer: ChromesthesiaVisualizer,
+    tuner_visualizer: TunerVisualizer,
+    oscilloscope_visualizer: OscilloscopeVisualizer,
+    ring_oscilloscope_visualizer: RingOscilloscopeVisualizer,
+    radial_spectrum_visualizer: RadialSpectrumVisualizer,
+    ambient_visualizer: AmbientVisualizer,
 
     selected_visualizer: String,
 
+    // Seconds of silence before switching to the ambient visualizer, or 0 to never switch.
+    idle_timeout_seconds: f32,
+    idle: bool,
+
     rotate_visualizers: bool,
     last_rotation: time::Instant,
+    last_update: time::Instant,
 }
 
 impl Visualizer {
-    pub fn new(selected_visualizer: String) -> Visualizer {
+    pub fn new(selected_visualizer: String, idle_timeout_seconds: f32) -> Visualizer {
         let rotate_visualizers = selected_visualizer == "";
 
         let mut selected_visualizer = selected_visualizer;
@@ -59,11 +82,21 @@ impl Visualizer {
             trisymmetry_visualizer: TriSymmetryVisualizer::new(),
             quadsymmetry_visualizer: QuadSymmetryVisualizer::new(),
             pentasymmetry_visualizer: PentaSymmetryVisualizer::new(),
+            chromesthesia_visualizer: ChromesthesiaVisualizer::new(),
+            tuner_visualizer: TunerVisualizer::new(),
+            oscilloscope_visualizer: OscilloscopeVisualizer::new(),
+            ring_oscilloscope_visualizer: RingOscilloscopeVisualizer::new(),
+            radial_spectrum_visualizer: RadialSpectrumVisualizer::new(),
+            ambient_visualizer: AmbientVisualizer::new(),
 
             selected_visualizer,
 
+            idle_timeout_seconds,
+            idle: false,
+
             rotate_visualizers,
             last_rotation: time::Instant::now(),
+            last_update: time::Instant::now(),
         }
     }
 
@@ -99,14 +132,20 @@ impl Visualizer {
                 texture, 0,
             ));
 
-            let mut all_visualizers: [&mut dyn SubVisualizer; 7] = [
+            let mut all_visualizers: [&mut dyn SubVisualizer; 13] = [
                 &mut self.equalizer_visualizer,
                 &mut self.power_circles_visualizer,
                 &mut self.smiley_visualizer,
                 &mut self.bisymmetry_visualizer,
                 &mut self.trisymmetry_visualizer,
                 &mut self.quadsymmetry_visualizer,
-                &mut self.pentasymmetry_visualizer];
+                &mut self.pentasymmetry_visualizer,
+                &mut self.chromesthesia_visualizer,
+                &mut self.tuner_visualizer,
+                &mut self.oscilloscope_visualizer,
+                &mut self.ring_oscilloscope_visualizer,
+                &mut self.radial_spectrum_visualizer,
+                &mut self.ambient_visualizer];
             for visualizer in all_visualizers.iter_mut() {
                 let (program_id, framebuffer_id) = visualizer.setup(gl, framebuffer);
                 visualizer.post_setup(program_id, framebuffer_id);
@@ -115,7 +154,15 @@ impl Visualizer {
     }
 
     pub fn update(&mut self, audio_frame: audio::AudioFrame) {
-        self.active_visualizer().update(audio_frame.clone());
+        // Switch back the moment the music returns.
+        self.idle = self.idle_timeout_seconds > 0.0 &&
+            audio_frame.idle_seconds >= self.idle_timeout_seconds;
+
+        let elapsed = self.last_update.elapsed();
+        let seconds = elapsed.as_secs() as f32 + elapsed.subsec_nanos() as f32 / 1_000_000_000.0;
+        self.last_update = time::Instant::now();
+
+        self.active_visualizer().update(audio_frame.clone(), seconds);
     }
 
     pub fn render_to_texture(&mut self, gl: &gfx::gl::Gl) -> u32 {
@@ -125,6 +172,10 @@ impl Visualizer {
     }
 
     fn active_visualizer(&mut self) -> &mut dyn SubVisualizer {
+        if self.idle {
+            return &mut self.ambient_visualizer;
+        }
+
         if self.rotate_visualizers &&
             self.last_rotation.elapsed() > TIME_PER_VISUALIZER {
             match self.selected_visualizer.as_ref() {
@@ -147,6 +198,12 @@ impl Visualizer {
             "trisymmetry" => &mut self.trisymmetry_visualizer,
             "quadsymmetry" => &mut self.quadsymmetry_visualizer,
             "pentasymmetry" => &mut self.pentasymmetry_visualizer,
+            "chromesthesia" => &mut self.chromesthesia_visualizer,
+            "tuner" => &mut self.tuner_visualizer,
+            "oscilloscope" => &mut self.oscilloscope_visualizer,
+            "oscilloscope_ring" => &mut self.ring_oscilloscope_visualizer,
+            "radial_spectrum" => &mut self.radial_spectrum_visualizer,
+            "ambient" => &mut self.ambient_visualizer,
 
             _ => &mut self.equalizer_visualizer,
         }